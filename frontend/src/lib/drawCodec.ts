@@ -0,0 +1,149 @@
+import type { IDrawOp } from "./types/server";
+
+// Mirrors server/src/codec.rs, see there for the frame layout
+const BEGIN = 0x01;
+const POINTS = 0x02;
+const POINT = 0x03;
+const END = 0x04;
+const CLEAR = 0x05;
+const UNDO = 0x06;
+const REDO = 0x07;
+const FILL = 0x08;
+const LINE = 0x09;
+const RECT = 0x0a;
+const ELLIPSE = 0x0b;
+
+// fixed point units per canvas width or height
+const COORD_SCALE = 4096;
+
+const quantize = (value: number) =>
+  Math.min(Math.max(Math.round(value * COORD_SCALE), 0), 0xffff);
+
+export const encodeOps = (ops: IDrawOp[]): Uint8Array => {
+  const out: number[] = [];
+  let cursor = [0, 0];
+
+  const pushPoint = (x: number, y: number) => {
+    const point = [quantize(x), quantize(y)];
+    out.push(point[0] >> 8, point[0] & 0xff, point[1] >> 8, point[1] & 0xff);
+    return point;
+  };
+
+  let i = 0;
+  while (i < ops.length) {
+    const op = ops[i];
+    if (op.op === "Point") {
+      const deltas: number[][] = [];
+      while (i < ops.length && deltas.length < 255) {
+        const next = ops[i];
+        if (next.op !== "Point") break;
+        const point = [quantize(next.x), quantize(next.y)];
+        const dx = point[0] - cursor[0];
+        const dy = point[1] - cursor[1];
+        if (dx < -128 || dx > 127 || dy < -128 || dy > 127) break;
+        deltas.push([dx, dy]);
+        cursor = point;
+        i++;
+      }
+      if (deltas.length === 0) {
+        out.push(POINT);
+        cursor = pushPoint(op.x, op.y);
+        i++;
+      } else {
+        out.push(POINTS, deltas.length);
+        deltas.forEach(([dx, dy]) => out.push(dx & 0xff, dy & 0xff));
+      }
+      continue;
+    }
+
+    if (op.op === "Begin") {
+      out.push(BEGIN);
+      cursor = pushPoint(op.x, op.y);
+      out.push(op.color, op.size, op.eraser ? 1 : 0);
+    } else if (op.op === "End") {
+      out.push(END);
+    } else if (op.op === "Clear") {
+      out.push(CLEAR);
+    } else if (op.op === "Undo") {
+      out.push(UNDO);
+    } else if (op.op === "Redo") {
+      out.push(REDO);
+    } else if (op.op === "Fill") {
+      out.push(FILL);
+      pushPoint(op.x, op.y);
+      out.push(op.color);
+    } else {
+      out.push(op.op === "Line" ? LINE : op.op === "Rect" ? RECT : ELLIPSE);
+      pushPoint(op.x1, op.y1);
+      pushPoint(op.x2, op.y2);
+      out.push(op.color, op.size);
+      if (op.op !== "Line") out.push(op.filled ? 1 : 0);
+    }
+    i++;
+  }
+  return new Uint8Array(out);
+};
+
+export const decodeOps = (buffer: ArrayBuffer): IDrawOp[] => {
+  const view = new DataView(buffer);
+  const ops: IDrawOp[] = [];
+  let pos = 0;
+  let cursor = [0, 0];
+
+  const u8 = () => view.getUint8(pos++);
+  const i8 = () => view.getInt8(pos++);
+  const unquantize = (value: number) => value / COORD_SCALE;
+  const point = () => {
+    const p = [view.getUint16(pos), view.getUint16(pos + 2)];
+    pos += 4;
+    return p;
+  };
+
+  while (pos < view.byteLength) {
+    const tag = u8();
+    if (tag === BEGIN) {
+      cursor = point();
+      ops.push({
+        op: "Begin",
+        x: unquantize(cursor[0]),
+        y: unquantize(cursor[1]),
+        color: u8(),
+        size: u8(),
+        eraser: (u8() & 1) !== 0,
+      });
+    } else if (tag === POINTS) {
+      const count = u8();
+      for (let n = 0; n < count; n++) {
+        cursor = [cursor[0] + i8(), cursor[1] + i8()];
+        ops.push({ op: "Point", x: unquantize(cursor[0]), y: unquantize(cursor[1]) });
+      }
+    } else if (tag === POINT) {
+      cursor = point();
+      ops.push({ op: "Point", x: unquantize(cursor[0]), y: unquantize(cursor[1]) });
+    } else if (tag === END) {
+      ops.push({ op: "End" });
+    } else if (tag === CLEAR) {
+      ops.push({ op: "Clear" });
+    } else if (tag === UNDO) {
+      ops.push({ op: "Undo" });
+    } else if (tag === REDO) {
+      ops.push({ op: "Redo" });
+    } else if (tag === FILL) {
+      const [x, y] = point().map(unquantize);
+      ops.push({ op: "Fill", x, y, color: u8() });
+    } else if (tag === LINE || tag === RECT || tag === ELLIPSE) {
+      const [x1, y1] = point().map(unquantize);
+      const [x2, y2] = point().map(unquantize);
+      const shape = { x1, y1, x2, y2, color: u8(), size: u8() };
+      if (tag === LINE) {
+        ops.push({ op: "Line", ...shape });
+      } else {
+        const filled = (u8() & 1) !== 0;
+        ops.push({ op: tag === RECT ? "Rect" : "Ellipse", ...shape, filled });
+      }
+    } else {
+      break;
+    }
+  }
+  return ops;
+};
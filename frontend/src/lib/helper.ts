@@ -1,7 +1,24 @@
-import type { IMessageType } from "./types/server";
+import type { IMessageType, ISystemEvent } from "./types/server";
 
 export const getMsgType = (category: string, type: string): IMessageType => {
     const obj: IMessageType = {};
     obj[category] = type;
     return obj
 };
+
+export const describeEvent = (event: ISystemEvent): string => {
+    switch (event.event) {
+        case "player_joined":
+            return `${event.username} joined`;
+        case "player_left":
+            return `${event.username} ${event.kicked ? "was kicked" : "left"}`;
+        case "player_reconnected":
+            return `${event.username} reconnected`;
+        case "player_guessed":
+            return `${event.username} guessed the word`;
+        case "owner_changed":
+            return `${event.username} is now the room owner`;
+        case "turn_started":
+            return `Round ${event.round} of ${event.total_rounds}, ${event.drawer_name} is drawing`;
+    }
+};
@@ -13,20 +13,144 @@ export interface IServerMsg {
   content: string;
 }
 
+export interface IGuessRejectedMsg {
+  reason: string;
+  guesses_left: number;
+}
+
 export interface IGuessMsg {
   username: string;
   user_id: string;
   content: string;
   correct?: boolean;
+  // sent on the channel only players who know the word can see
+  guessed_only?: boolean;
+  // a whisper between two players
+  whisper?: boolean;
+}
+
+// a whisper, sent to its recipient and back to its sender
+export interface IWhisperedMsg {
+  from_id: string;
+  from_name: string;
+  to_id: string;
+  content: string;
+}
+
+// a chat message or system event as it was sent, received in a ChatHistory list after connecting
+export interface IChatEntry {
+  msg_type: IMessageType;
+  content: string;
+  at: number;
+}
+
+// sent privately to a player whose chat and guesses are dropped
+export interface IMutedMsg {
+  reason: string;
+  // when the mute runs out, null for the rest of the game
+  until: number | null;
+}
+
+// private reply to a chat command such as /help
+export interface ICommandReplyMsg {
+  ok: boolean;
+  msg: string;
+}
+
+// the word with the letters revealed so far, others as underscores
+export interface IHintMsg {
+  hint: string;
+}
+
+// announced by the server, tagged by `event`
+export type ISystemEvent =
+  | { event: "player_joined"; user_id: string; username: string }
+  | { event: "player_left"; user_id: string; username: string; kicked: boolean }
+  | { event: "player_reconnected"; user_id: string; username: string }
+  | { event: "player_guessed"; user_id: string; username: string }
+  | { event: "owner_changed"; owner_id: string; username: string }
+  | {
+      event: "turn_started";
+      round: number;
+      total_rounds: number;
+      drawer_id: string;
+      drawer_name: string;
+    };
+
+export type ISystemEventMsg = ISystemEvent & { at: number };
+
+// emoji guessers can react with, matching the server's list
+export const REACTIONS = ["👍", "👎", "😂", "😮", "🔥", "❤️"];
+
+export interface IReactionMsg {
+  user_id: string;
+  emoji: string;
+}
+
+// ratings of the last turn's drawing so far
+export interface IDrawingRatedMsg {
+  round: number;
+  drawer_id: string;
+  thumbs_up: number;
+  thumbs_down: number;
+}
+
+// an open vote to kick target_id, sent with VoteKickStarted and VoteKickProgress
+export interface IVoteKickState {
+  target_id: string;
+  initiator_id: string;
+  yes: number;
+  no: number;
+  required: number;
+  expires_at: number;
+}
+
+export interface IVoteKickResultMsg {
+  target_id: string;
+  kicked: boolean;
+  yes: number;
+  no: number;
+  required: number;
 }
 
-export interface IDrawMsg {
+export interface IShape {
+  x1: number;
+  y1: number;
+  x2: number;
+  y2: number;
   color: number;
   size: number;
-  x: number;
-  y: number;
-  beginning: boolean;
-  end: boolean;
+}
+
+export type IDrawOp =
+  | {
+      op: "Begin";
+      x: number;
+      y: number;
+      color: number;
+      size: number;
+      eraser?: boolean;
+    }
+  | { op: "Point"; x: number; y: number }
+  | { op: "End" }
+  | { op: "Fill"; x: number; y: number; color: number }
+  | ({ op: "Line" } & IShape)
+  | ({ op: "Rect"; filled?: boolean } & IShape)
+  | ({ op: "Ellipse"; filled?: boolean } & IShape)
+  | { op: "Clear" }
+  | { op: "Undo" }
+  | { op: "Redo" };
+
+// GET /gallery/{room_id}/{index}/timelapse, offsets are relative to the start of the turn
+export type ITimedOp = IDrawOp & { offset_ms: number };
+
+export interface ITimelapse {
+  round: number;
+  word: string;
+  drawer_id: string;
+  canvas: ICanvasSettings;
+  duration_ms: number;
+  ops: ITimedOp[];
 }
 
 export interface IGameMsg {
@@ -55,21 +179,127 @@ export interface IGameState {
   title: string;
   correct_word: string;
   round_start_time: number;
+  max_guesses?: number | null;
+  wrong_guess_penalty?: number;
+  // points the drawer gets for every thumbs up on their drawing
+  rating_bonus?: number;
+  disable_whispers?: boolean;
+  canvas?: ICanvasSettings;
+  // extra words the server filters in this room, only sent by the owner
+  blocked_terms?: string[];
 }
 
+// draw coordinates are normalized, 0 to 1 across the canvas width and height
+export interface ICanvasSettings {
+  // width divided by height
+  aspect_ratio: number;
+  // RGB colors, indexed by a draw op's color
+  palette: [number, number, number][];
+  // brush widths on a 600 pixel wide canvas, indexed by a draw op's size
+  brush_widths: number[];
+}
+
+export const DEFAULT_CANVAS: ICanvasSettings = {
+  aspect_ratio: 1,
+  palette: [
+    [0, 0, 0],
+    [255, 255, 255],
+    [255, 0, 0],
+    [0, 128, 0],
+    [0, 0, 255],
+  ],
+  brush_widths: [5, 10, 15],
+};
+
 export const MessageTypes = {
-  Relay: ["Info", "Draw", "Text"],
+  Relay: ["Draw", "Text", "GuessedText"],
   Game: ["StartGame", "GameState"],
   Data: ["UserData"],
 };
 export interface IPlayer {
   username: string;
   score: number;
-  prev_score: number
   active: boolean;
+  thumbs_up?: number;
+  thumbs_down?: number;
 }
 
 export interface PlayerData {
   user_id: string;
   player: IPlayer;
 }
+
+export interface IGuesserResult {
+  user_id: string;
+  points: number;
+  elapsed_ms: number;
+}
+
+export interface IGuessAttempt {
+  user_id: string;
+  text: string;
+  offset_ms: number;
+  correct: boolean;
+}
+
+export interface ITurnResults {
+  round: number;
+  word: string;
+  drawer_id: string;
+  drawer_points: number;
+  guessers: IGuesserResult[];
+  not_guessed: string[];
+  guesses: IGuessAttempt[];
+  penalties: { [key: string]: number };
+  reactions: { [key: string]: number };
+  // true for a thumbs up
+  ratings: { [key: string]: boolean };
+}
+
+export interface IStanding {
+  rank: number;
+  user_id: string;
+  username: string;
+  score: number;
+  tied: boolean;
+}
+
+export interface IRoundHistory {
+  round: number;
+  scores: { [key: string]: number };
+  gained: { [key: string]: number };
+}
+
+export interface IAward {
+  kind: "FastestGuesser" | "BestDrawer" | "MostCorrectGuesses";
+  user_ids: string[];
+  value: number;
+}
+
+export interface IFinalResults {
+  room_id: string;
+  title: string;
+  standings: IStanding[];
+  rounds: IRoundHistory[];
+  turns: ITurnResults[];
+  awards: IAward[];
+}
+
+export interface IDrawing {
+  round: number;
+  word: string;
+  drawer_id: string;
+  canvas: ICanvasSettings;
+  drawer_name: string;
+  guess_count: number;
+  created_at: number;
+  duration_ms: number;
+}
+
+// GET /gallery/{room_id}
+export interface IGallery {
+  room_id: string;
+  title: string;
+  updated_at: number;
+  drawings: IDrawing[];
+}
@@ -0,0 +1,401 @@
+//! Compact binary encoding of draw operations, used over websocket binary frames.
+//!
+//! A frame is a sequence of operations, each starting with a tag byte.
+//! Normalized coordinates are sent as big endian `u16` fixed point, in units of `1 / COORD_SCALE`.
+//! Runs of points are sent as `i8` deltas from the previous point.
+//!
+//! | tag    | op       | payload                                     |
+//! |--------|----------|---------------------------------------------|
+//! | `0x01` | Begin    | x, y, color `u8`, size `u8`, flags `u8`     |
+//! | `0x02` | Points   | count `u8`, count * (dx `i8`, dy `i8`)      |
+//! | `0x03` | Point    | x, y                                        |
+//! | `0x04` | End      |                                             |
+//! | `0x05` | Clear    |                                             |
+//! | `0x06` | Undo     |                                             |
+//! | `0x07` | Redo     |                                             |
+//! | `0x08` | Fill     | x, y, color `u8`                            |
+//! | `0x09` | Line     | x1, y1, x2, y2, color `u8`, size `u8`       |
+//! | `0x0A` | Rect     | x1, y1, x2, y2, color `u8`, size `u8`, flags `u8` |
+//! | `0x0B` | Ellipse  | x1, y1, x2, y2, color `u8`, size `u8`, flags `u8` |
+//!
+//! Flag bit `0x01` is `eraser` for Begin and `filled` for shapes.
+
+use crate::draw::DrawOp;
+
+const BEGIN: u8 = 0x01;
+const POINTS: u8 = 0x02;
+const POINT: u8 = 0x03;
+const END: u8 = 0x04;
+const CLEAR: u8 = 0x05;
+const UNDO: u8 = 0x06;
+const REDO: u8 = 0x07;
+const FILL: u8 = 0x08;
+const LINE: u8 = 0x09;
+const RECT: u8 = 0x0A;
+const ELLIPSE: u8 = 0x0B;
+
+const FLAG_SET: u8 = 0x01;
+
+/// Fixed point units per canvas width or height
+const COORD_SCALE: f64 = 4096.0;
+
+/// Encodes operations into a single binary frame
+pub fn encode_ops(ops: &[DrawOp]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut cursor = (0, 0);
+    let mut i = 0;
+    while i < ops.len() {
+        match &ops[i] {
+            DrawOp::Begin {
+                x,
+                y,
+                color,
+                size,
+                eraser,
+            } => {
+                out.push(BEGIN);
+                cursor = push_point(&mut out, *x, *y);
+                out.push(*color as u8);
+                out.push(*size as u8);
+                out.push(flags(*eraser));
+            }
+            DrawOp::Point { .. } => {
+                // pack as many following points as fit in deltas
+                let mut deltas = Vec::new();
+                while let Some(DrawOp::Point { x, y }) = ops.get(i) {
+                    let next = (quantize(*x), quantize(*y));
+                    let dx = i8::try_from(next.0 as i32 - cursor.0 as i32);
+                    let dy = i8::try_from(next.1 as i32 - cursor.1 as i32);
+                    match (dx, dy) {
+                        (Ok(dx), Ok(dy)) if deltas.len() < u8::MAX as usize => {
+                            deltas.push((dx, dy));
+                            cursor = next;
+                            i += 1;
+                        }
+                        _ => break,
+                    }
+                }
+
+                if deltas.is_empty() {
+                    if let DrawOp::Point { x, y } = &ops[i] {
+                        out.push(POINT);
+                        cursor = push_point(&mut out, *x, *y);
+                        i += 1;
+                    }
+                } else {
+                    out.push(POINTS);
+                    out.push(deltas.len() as u8);
+                    for (dx, dy) in deltas {
+                        out.push(dx as u8);
+                        out.push(dy as u8);
+                    }
+                }
+                continue;
+            }
+            DrawOp::End => out.push(END),
+            DrawOp::Clear => out.push(CLEAR),
+            DrawOp::Undo => out.push(UNDO),
+            DrawOp::Redo => out.push(REDO),
+            DrawOp::Fill { x, y, color } => {
+                out.push(FILL);
+                push_point(&mut out, *x, *y);
+                out.push(*color as u8);
+            }
+            DrawOp::Line {
+                x1,
+                y1,
+                x2,
+                y2,
+                color,
+                size,
+            } => {
+                out.push(LINE);
+                push_point(&mut out, *x1, *y1);
+                push_point(&mut out, *x2, *y2);
+                out.push(*color as u8);
+                out.push(*size as u8);
+            }
+            DrawOp::Rect {
+                x1,
+                y1,
+                x2,
+                y2,
+                color,
+                size,
+                filled,
+            }
+            | DrawOp::Ellipse {
+                x1,
+                y1,
+                x2,
+                y2,
+                color,
+                size,
+                filled,
+            } => {
+                out.push(if matches!(ops[i], DrawOp::Rect { .. }) {
+                    RECT
+                } else {
+                    ELLIPSE
+                });
+                push_point(&mut out, *x1, *y1);
+                push_point(&mut out, *x2, *y2);
+                out.push(*color as u8);
+                out.push(*size as u8);
+                out.push(flags(*filled));
+            }
+        }
+        i += 1;
+    }
+    out
+}
+
+/// Decodes a binary frame, returning `None` if it is truncated or has an unknown tag
+pub fn decode_ops(data: &[u8]) -> Option<Vec<DrawOp>> {
+    let mut reader = Reader { data, pos: 0 };
+    let mut ops = Vec::new();
+    let mut cursor = (0, 0);
+    while let Some(tag) = reader.u8() {
+        let op = match tag {
+            BEGIN => {
+                cursor = reader.point()?;
+                DrawOp::Begin {
+                    x: unquantize(cursor.0),
+                    y: unquantize(cursor.1),
+                    color: reader.u8()? as usize,
+                    size: reader.u8()? as usize,
+                    eraser: reader.u8()? & FLAG_SET != 0,
+                }
+            }
+            POINTS => {
+                let count = reader.u8()?;
+                for _ in 0..count {
+                    let dx = reader.u8()? as i8 as i32;
+                    let dy = reader.u8()? as i8 as i32;
+                    cursor = (
+                        u16::try_from(cursor.0 as i32 + dx).ok()?,
+                        u16::try_from(cursor.1 as i32 + dy).ok()?,
+                    );
+                    ops.push(DrawOp::Point {
+                        x: unquantize(cursor.0),
+                        y: unquantize(cursor.1),
+                    });
+                }
+                continue;
+            }
+            POINT => {
+                cursor = reader.point()?;
+                DrawOp::Point {
+                    x: unquantize(cursor.0),
+                    y: unquantize(cursor.1),
+                }
+            }
+            END => DrawOp::End,
+            CLEAR => DrawOp::Clear,
+            UNDO => DrawOp::Undo,
+            REDO => DrawOp::Redo,
+            FILL => {
+                let (x, y) = reader.point()?;
+                DrawOp::Fill {
+                    x: unquantize(x),
+                    y: unquantize(y),
+                    color: reader.u8()? as usize,
+                }
+            }
+            LINE => {
+                let (x1, y1) = reader.point()?;
+                let (x2, y2) = reader.point()?;
+                DrawOp::Line {
+                    x1: unquantize(x1),
+                    y1: unquantize(y1),
+                    x2: unquantize(x2),
+                    y2: unquantize(y2),
+                    color: reader.u8()? as usize,
+                    size: reader.u8()? as usize,
+                }
+            }
+            RECT | ELLIPSE => {
+                let (x1, y1) = reader.point()?;
+                let (x2, y2) = reader.point()?;
+                let color = reader.u8()? as usize;
+                let size = reader.u8()? as usize;
+                let filled = reader.u8()? & FLAG_SET != 0;
+                let (x1, y1, x2, y2) = (
+                    unquantize(x1),
+                    unquantize(y1),
+                    unquantize(x2),
+                    unquantize(y2),
+                );
+                if tag == RECT {
+                    DrawOp::Rect {
+                        x1,
+                        y1,
+                        x2,
+                        y2,
+                        color,
+                        size,
+                        filled,
+                    }
+                } else {
+                    DrawOp::Ellipse {
+                        x1,
+                        y1,
+                        x2,
+                        y2,
+                        color,
+                        size,
+                        filled,
+                    }
+                }
+            }
+            _ => return None,
+        };
+        ops.push(op);
+    }
+    Some(ops)
+}
+
+fn quantize(value: f64) -> u16 {
+    (value * COORD_SCALE).round().clamp(0.0, u16::MAX as f64) as u16
+}
+
+fn unquantize(value: u16) -> f64 {
+    value as f64 / COORD_SCALE
+}
+
+fn flags(set: bool) -> u8 {
+    if set {
+        FLAG_SET
+    } else {
+        0
+    }
+}
+
+fn push_point(out: &mut Vec<u8>, x: f64, y: f64) -> (u16, u16) {
+    let point = (quantize(x), quantize(y));
+    out.extend_from_slice(&point.0.to_be_bytes());
+    out.extend_from_slice(&point.1.to_be_bytes());
+    point
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn u8(&mut self) -> Option<u8> {
+        let byte = *self.data.get(self.pos)?;
+        self.pos += 1;
+        Some(byte)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let bytes = self.data.get(self.pos..self.pos + 2)?;
+        self.pos += 2;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn point(&mut self) -> Option<(u16, u16)> {
+        Some((self.u16()?, self.u16()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A coordinate that survives quantization exactly
+    fn coord(units: u16) -> f64 {
+        unquantize(units)
+    }
+
+    fn point(x: u16, y: u16) -> DrawOp {
+        DrawOp::Point {
+            x: coord(x),
+            y: coord(y),
+        }
+    }
+
+    #[test]
+    fn round_trips_every_op() {
+        let ops = vec![
+            DrawOp::Begin {
+                x: coord(100),
+                y: coord(200),
+                color: 2,
+                size: 1,
+                eraser: true,
+            },
+            point(110, 190),
+            point(3000, 190),
+            DrawOp::End,
+            DrawOp::Fill {
+                x: coord(2048),
+                y: coord(4096),
+                color: 4,
+            },
+            DrawOp::Line {
+                x1: coord(0),
+                y1: coord(1),
+                x2: coord(4095),
+                y2: coord(4096),
+                color: 0,
+                size: 2,
+            },
+            DrawOp::Rect {
+                x1: coord(10),
+                y1: coord(20),
+                x2: coord(30),
+                y2: coord(40),
+                color: 1,
+                size: 0,
+                filled: true,
+            },
+            DrawOp::Ellipse {
+                x1: coord(10),
+                y1: coord(20),
+                x2: coord(30),
+                y2: coord(40),
+                color: 3,
+                size: 1,
+                filled: false,
+            },
+            DrawOp::Clear,
+            DrawOp::Undo,
+            DrawOp::Redo,
+        ];
+        assert_eq!(decode_ops(&encode_ops(&ops)), Some(ops));
+    }
+
+    #[test]
+    fn packs_nearby_points_as_deltas() {
+        let mut ops = vec![DrawOp::Begin {
+            x: coord(1000),
+            y: coord(1000),
+            color: 0,
+            size: 0,
+            eraser: false,
+        }];
+        // more points than fit in a single run
+        ops.extend((1..=300).map(|i| point(1000 + i, 1000 - i)));
+
+        let encoded = encode_ops(&ops);
+        assert_eq!(encoded[8], POINTS);
+        assert_eq!(encoded[9], u8::MAX);
+        assert!(encoded.len() < 8 + 300 * 3);
+        assert_eq!(decode_ops(&encoded), Some(ops));
+    }
+
+    #[test]
+    fn rejects_truncated_and_unknown_frames() {
+        let encoded = encode_ops(&[DrawOp::Fill {
+            x: 0.5,
+            y: 0.5,
+            color: 1,
+        }]);
+        assert!(decode_ops(&encoded[..encoded.len() - 1]).is_none());
+        assert!(decode_ops(&[0xFF]).is_none());
+        assert_eq!(decode_ops(&[]), Some(Vec::new()));
+    }
+}
@@ -0,0 +1,211 @@
+//! Chat commands, sent as chat messages or guesses starting with `/`
+
+use uuid::Uuid;
+
+use crate::room::Room;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Permission {
+    Anyone,
+    Owner,
+    /// The drawer of the turn in progress
+    Drawer,
+}
+
+impl Permission {
+    pub fn allows(&self, room: &Room, user_id: &Uuid) -> bool {
+        match self {
+            Permission::Anyone => true,
+            Permission::Owner => room.owner == *user_id,
+            Permission::Drawer => room.can_draw(user_id),
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            Permission::Anyone => "anyone",
+            Permission::Owner => "the room owner",
+            Permission::Drawer => "the drawer",
+        }
+    }
+}
+
+pub enum Command {
+    Kick(String),
+    Skip,
+    Mute(String),
+    Hint,
+    Rounds(usize),
+    Help,
+}
+
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub help: &'static str,
+    pub permission: Permission,
+    /// Parses the arguments, returning `None` if they don't fit `usage`
+    parse: fn(&str) -> Option<Command>,
+}
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "kick",
+        usage: "/kick <player>",
+        help: "Removes a player from the room",
+        permission: Permission::Owner,
+        parse: |args| (!args.is_empty()).then(|| Command::Kick(args.to_string())),
+    },
+    CommandSpec {
+        name: "skip",
+        usage: "/skip",
+        help: "Ends the current turn",
+        permission: Permission::Owner,
+        parse: |args| args.is_empty().then_some(Command::Skip),
+    },
+    CommandSpec {
+        name: "mute",
+        usage: "/mute <player>",
+        help: "Silences a player's chat and guesses for the rest of the game",
+        permission: Permission::Owner,
+        parse: |args| (!args.is_empty()).then(|| Command::Mute(args.to_string())),
+    },
+    CommandSpec {
+        name: "hint",
+        usage: "/hint",
+        help: "Reveals a letter of the word to everyone",
+        permission: Permission::Drawer,
+        parse: |args| args.is_empty().then_some(Command::Hint),
+    },
+    CommandSpec {
+        name: "rounds",
+        usage: "/rounds <number>",
+        help: "Changes the number of rounds in the game",
+        permission: Permission::Owner,
+        parse: |args| args.parse().ok().map(Command::Rounds),
+    },
+    CommandSpec {
+        name: "help",
+        usage: "/help",
+        help: "Lists the commands you can use",
+        permission: Permission::Anyone,
+        parse: |_| Some(Command::Help),
+    },
+];
+
+/// Parses a chat message as a command.
+/// Returns `None` if it isn't one, or the reply for a command that can't be run.
+pub fn parse(text: &str, room: &Room, user_id: &Uuid) -> Option<Result<Command, String>> {
+    let text = text.trim().strip_prefix('/')?;
+    let (name, args) = match text.split_once(char::is_whitespace) {
+        Some((name, args)) => (name, args.trim()),
+        None => (text, ""),
+    };
+    let name = name.to_lowercase();
+
+    let Some(spec) = COMMANDS.iter().find(|spec| spec.name == name) else {
+        return Some(Err(format!("Unknown command /{}, try /help", name)));
+    };
+    if !spec.permission.allows(room, user_id) {
+        return Some(Err(format!(
+            "Only {} can use /{}",
+            spec.permission.describe(),
+            spec.name
+        )));
+    }
+    Some((spec.parse)(args).ok_or(format!("Usage: {}", spec.usage)))
+}
+
+/// The commands a player may use, one per line
+pub fn help(room: &Room, user_id: &Uuid) -> String {
+    COMMANDS
+        .iter()
+        .filter(|spec| spec.permission.allows(room, user_id))
+        .map(|spec| format!("{} - {}", spec.usage, spec.help))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::room::test_rooms::started_room;
+
+    /// A started room whose drawer also owns it, and a player who is only guessing
+    fn room() -> (Room, Uuid, Uuid) {
+        let (mut room, ids) = started_room(2);
+        room.owner = ids[0];
+        (room, ids[0], ids[1])
+    }
+
+    #[test]
+    fn ignores_plain_messages() {
+        let (room, owner, _) = room();
+        assert!(parse("hello /kick", &room, &owner).is_none());
+    }
+
+    #[test]
+    fn parses_names_and_arguments() {
+        let (room, owner, _) = room();
+        assert!(matches!(
+            parse("  /KICK  some one ", &room, &owner),
+            Some(Ok(Command::Kick(name))) if name == "some one"
+        ));
+        assert!(matches!(
+            parse("/rounds 5", &room, &owner),
+            Some(Ok(Command::Rounds(5)))
+        ));
+        assert!(matches!(
+            parse("/help", &room, &owner),
+            Some(Ok(Command::Help))
+        ));
+    }
+
+    #[test]
+    fn replies_to_bad_commands() {
+        let (room, owner, _) = room();
+        assert!(matches!(
+            parse("/dance", &room, &owner),
+            Some(Err(reply)) if reply.starts_with("Unknown command /dance")
+        ));
+        assert!(matches!(
+            parse("/rounds many", &room, &owner),
+            Some(Err(reply)) if reply == "Usage: /rounds <number>"
+        ));
+        assert!(matches!(
+            parse("/kick", &room, &owner),
+            Some(Err(reply)) if reply == "Usage: /kick <player>"
+        ));
+    }
+
+    #[test]
+    fn checks_permissions() {
+        let (room, owner, guesser) = room();
+        assert!(matches!(
+            parse("/skip", &room, &guesser),
+            Some(Err(reply)) if reply == "Only the room owner can use /skip"
+        ));
+        assert!(matches!(
+            parse("/hint", &room, &guesser),
+            Some(Err(reply)) if reply == "Only the drawer can use /hint"
+        ));
+        assert!(matches!(
+            parse("/hint", &room, &owner),
+            Some(Ok(Command::Hint))
+        ));
+        assert!(matches!(
+            parse("/help", &room, &guesser),
+            Some(Ok(Command::Help))
+        ));
+    }
+
+    #[test]
+    fn help_lists_allowed_commands() {
+        let (room, owner, guesser) = room();
+        assert_eq!(
+            help(&room, &guesser),
+            "/help - Lists the commands you can use"
+        );
+        assert_eq!(help(&room, &owner).lines().count(), COMMANDS.len());
+    }
+}
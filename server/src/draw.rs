@@ -0,0 +1,235 @@
+//! Draw coordinates are normalized, x and y run from 0 to 1 across the canvas width and height.
+//! Clients scale them to their own canvas size, whose shape is set by the room's `CanvasSettings`.
+
+use serde::{Deserialize, Serialize};
+
+/// Canvas width that brush widths are given for, they scale with the actual width
+pub const REFERENCE_WIDTH: f64 = 600.0;
+
+/// Supported range of canvas aspect ratios
+const MIN_ASPECT_RATIO: f64 = 0.5;
+const MAX_ASPECT_RATIO: f64 = 2.0;
+
+/// Palette of a room that doesn't set one, as RGB
+const DEFAULT_PALETTE: [[u8; 3]; 5] = [
+    [0, 0, 0],
+    [255, 255, 255],
+    [255, 0, 0],
+    [0, 128, 0],
+    [0, 0, 255],
+];
+
+/// Brush widths of a room that doesn't set them
+const DEFAULT_BRUSH_WIDTHS: [f32; 3] = [5.0, 10.0, 15.0];
+
+/// Limits on room palettes and brush sets. Indices have to fit the binary protocol's `u8`.
+const MAX_PALETTE_SIZE: usize = 32;
+const MAX_BRUSH_SIZES: usize = 8;
+const MAX_BRUSH_WIDTH: f32 = 100.0;
+
+/// Canvas shape, palette and brushes of a room, sent to clients when they join
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CanvasSettings {
+    /// Width divided by height
+    pub aspect_ratio: f64,
+    /// Colors as RGB, indexed by a draw operation's `color`
+    #[serde(default = "default_palette")]
+    pub palette: Vec<[u8; 3]>,
+    /// Brush widths in pixels on a `REFERENCE_WIDTH` wide canvas, indexed by `size`
+    #[serde(default = "default_brush_widths")]
+    pub brush_widths: Vec<f32>,
+}
+
+fn default_palette() -> Vec<[u8; 3]> {
+    DEFAULT_PALETTE.to_vec()
+}
+
+fn default_brush_widths() -> Vec<f32> {
+    DEFAULT_BRUSH_WIDTHS.to_vec()
+}
+
+impl CanvasSettings {
+    pub fn default() -> CanvasSettings {
+        CanvasSettings {
+            aspect_ratio: 1.0,
+            palette: default_palette(),
+            brush_widths: default_brush_widths(),
+        }
+    }
+
+    /// Keeps the aspect ratio within the supported range,
+    /// and falls back to the default palette or brushes if the room's are unusable
+    pub fn validated(self) -> CanvasSettings {
+        let aspect_ratio = if self.aspect_ratio.is_finite() {
+            self.aspect_ratio.clamp(MIN_ASPECT_RATIO, MAX_ASPECT_RATIO)
+        } else {
+            1.0
+        };
+        let palette = if (1..=MAX_PALETTE_SIZE).contains(&self.palette.len()) {
+            self.palette
+        } else {
+            default_palette()
+        };
+        let valid_width = |width: &f32| *width > 0.0 && *width <= MAX_BRUSH_WIDTH;
+        let brush_widths = if (1..=MAX_BRUSH_SIZES).contains(&self.brush_widths.len())
+            && self.brush_widths.iter().all(valid_width)
+        {
+            self.brush_widths
+        } else {
+            default_brush_widths()
+        };
+        CanvasSettings {
+            aspect_ratio,
+            palette,
+            brush_widths,
+        }
+    }
+
+    /// Height in pixels of a canvas `width` pixels wide
+    pub fn height_for(&self, width: f64) -> f64 {
+        (width / self.aspect_ratio).round()
+    }
+
+    pub fn rgb(&self, color: usize) -> [u8; 3] {
+        self.palette.get(color).copied().unwrap_or_default()
+    }
+
+    pub fn brush_width(&self, size: usize) -> f32 {
+        self.brush_widths
+            .get(size)
+            .copied()
+            .unwrap_or(DEFAULT_BRUSH_WIDTHS[0])
+    }
+}
+
+/// A single drawing operation, sent as the content of `Relay(Draw)`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "op")]
+pub enum DrawOp {
+    /// Starts a freehand stroke, or erases along it when `eraser` is set
+    Begin {
+        x: f64,
+        y: f64,
+        color: usize,
+        size: usize,
+        #[serde(default)]
+        eraser: bool,
+    },
+    Point {
+        x: f64,
+        y: f64,
+    },
+    End,
+    /// Flood fills the area around (x, y)
+    Fill {
+        x: f64,
+        y: f64,
+        color: usize,
+    },
+    Line {
+        x1: f64,
+        y1: f64,
+        x2: f64,
+        y2: f64,
+        color: usize,
+        size: usize,
+    },
+    /// Rectangle with corners (x1, y1) and (x2, y2)
+    Rect {
+        x1: f64,
+        y1: f64,
+        x2: f64,
+        y2: f64,
+        color: usize,
+        size: usize,
+        #[serde(default)]
+        filled: bool,
+    },
+    /// Ellipse inside the box with corners (x1, y1) and (x2, y2)
+    Ellipse {
+        x1: f64,
+        y1: f64,
+        x2: f64,
+        y2: f64,
+        color: usize,
+        size: usize,
+        #[serde(default)]
+        filled: bool,
+    },
+    Clear,
+    /// Removes the last complete stroke
+    Undo,
+    /// Restores the last stroke removed by `Undo`
+    Redo,
+}
+
+/// A draw operation with the time it was relayed, relative to the start of the turn
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct TimedOp {
+    pub offset_ms: u128,
+    #[serde(flatten)]
+    pub op: DrawOp,
+}
+
+impl DrawOp {
+    /// Parses a draw operation, returning `None` if it is malformed
+    pub fn parse(content: &str) -> Option<DrawOp> {
+        serde_json::from_str::<DrawOp>(content).ok()
+    }
+
+    /// Whether the operation stays on the canvas and uses the room's palette and brushes
+    pub fn is_valid(&self, canvas: &CanvasSettings) -> bool {
+        let color_ok = |color: &usize| *color < canvas.palette.len();
+        let size_ok = |size: &usize| *size < canvas.brush_widths.len();
+        match self {
+            DrawOp::Begin {
+                x, y, color, size, ..
+            } => in_canvas(*x, *y) && color_ok(color) && size_ok(size),
+            DrawOp::Point { x, y } => in_canvas(*x, *y),
+            DrawOp::Fill { x, y, color } => in_canvas(*x, *y) && color_ok(color),
+            DrawOp::Line {
+                x1,
+                y1,
+                x2,
+                y2,
+                color,
+                size,
+            }
+            | DrawOp::Rect {
+                x1,
+                y1,
+                x2,
+                y2,
+                color,
+                size,
+                ..
+            }
+            | DrawOp::Ellipse {
+                x1,
+                y1,
+                x2,
+                y2,
+                color,
+                size,
+                ..
+            } => in_canvas(*x1, *y1) && in_canvas(*x2, *y2) && color_ok(color) && size_ok(size),
+            DrawOp::End | DrawOp::Clear | DrawOp::Undo | DrawOp::Redo => true,
+        }
+    }
+
+    /// Whether this operation starts a new stroke. Undo and redo work on whole strokes.
+    pub fn starts_stroke(&self) -> bool {
+        matches!(
+            self,
+            DrawOp::Begin { .. }
+                | DrawOp::Fill { .. }
+                | DrawOp::Line { .. }
+                | DrawOp::Rect { .. }
+                | DrawOp::Ellipse { .. }
+        )
+    }
+}
+
+fn in_canvas(x: f64, y: f64) -> bool {
+    (0.0..=1.0).contains(&x) && (0.0..=1.0).contains(&y)
+}
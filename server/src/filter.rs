@@ -0,0 +1,160 @@
+use std::{collections::HashSet, env, fs};
+
+/// File the default blocked terms are read from, one per line
+const DEFAULT_LIST_PATH: &str = "blocked_words.txt";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FilterMode {
+    /// Blocked terms are replaced with `*`
+    Mask,
+    /// Messages with blocked terms are refused
+    Reject,
+}
+
+pub enum Filtered {
+    Clean,
+    Masked(String),
+    Rejected,
+}
+
+/// Blocks terms in chat, guesses, usernames and room titles.
+/// Terms are matched case insensitively against whole words.
+#[derive(Clone, Debug)]
+pub struct WordFilter {
+    terms: HashSet<String>,
+    pub mode: FilterMode,
+}
+
+impl WordFilter {
+    pub fn new(terms: &[String], mode: FilterMode) -> WordFilter {
+        WordFilter {
+            terms: terms.iter().map(|term| normalize_term(term)).collect(),
+            mode,
+        }
+    }
+
+    /// Reads the list from `WORD_FILTER_FILE`, or `blocked_words.txt` by default,
+    /// and the mode from `WORD_FILTER_MODE` (`mask` or `reject`)
+    pub fn from_env() -> WordFilter {
+        let path = env::var("WORD_FILTER_FILE").unwrap_or(DEFAULT_LIST_PATH.to_string());
+        let terms: Vec<String> = match fs::read_to_string(&path) {
+            Ok(list) => list
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(str::to_string)
+                .collect(),
+            Err(_) => {
+                println!(
+                    "Could not read word filter list {}, filtering nothing",
+                    path
+                );
+                Vec::new()
+            }
+        };
+        let mode = match env::var("WORD_FILTER_MODE").as_deref() {
+            Ok("reject") => FilterMode::Reject,
+            Ok("mask") | Err(_) => FilterMode::Mask,
+            Ok(_) => {
+                println!("Invalid value for WORD_FILTER_MODE, using mask");
+                FilterMode::Mask
+            }
+        };
+        WordFilter::new(&terms, mode)
+    }
+
+    /// Checks text against the blocked terms and a room's `extra_terms`, using the filter's mode
+    pub fn check(&self, text: &str, extra_terms: &[String]) -> Filtered {
+        match self.mask(text, extra_terms) {
+            None => Filtered::Clean,
+            Some(_) if self.mode == FilterMode::Reject => Filtered::Rejected,
+            Some(masked) => Filtered::Masked(masked),
+        }
+    }
+
+    /// Text with blocked terms masked, for names that can't be rejected
+    pub fn masked(&self, text: &str, extra_terms: &[String]) -> String {
+        self.mask(text, extra_terms)
+            .unwrap_or_else(|| text.to_string())
+    }
+
+    /// Masks every blocked word, returning `None` if there were none
+    fn mask(&self, text: &str, extra_terms: &[String]) -> Option<String> {
+        let blocked = |word: &str| {
+            let word = normalize_term(word);
+            self.terms.contains(&word)
+                || extra_terms.iter().any(|term| normalize_term(term) == word)
+        };
+
+        let mut out = String::with_capacity(text.len());
+        let mut found = false;
+        let mut word = String::new();
+        let mut flush = |word: &mut String, out: &mut String| {
+            if !word.is_empty() && blocked(word) {
+                found = true;
+                out.extend(word.chars().map(|_| '*'));
+            } else {
+                out.push_str(word);
+            }
+            word.clear();
+        };
+        for c in text.chars() {
+            if c.is_alphanumeric() {
+                word.push(c);
+            } else {
+                flush(&mut word, &mut out);
+                out.push(c);
+            }
+        }
+        flush(&mut word, &mut out);
+
+        if found {
+            Some(out)
+        } else {
+            None
+        }
+    }
+}
+
+fn normalize_term(term: &str) -> String {
+    term.trim().to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(mode: FilterMode) -> WordFilter {
+        WordFilter::new(&["Bad".to_string(), " worse ".to_string()], mode)
+    }
+
+    #[test]
+    fn masks_whole_words_only() {
+        let filter = filter(FilterMode::Mask);
+        assert_eq!(filter.masked("bad, WORSE badge", &[]), "***, ***** badge");
+        assert!(matches!(filter.check("badminton", &[]), Filtered::Clean));
+        assert!(
+            matches!(filter.check("so bad!", &[]), Filtered::Masked(masked) if masked == "so ***!")
+        );
+    }
+
+    #[test]
+    fn applies_room_terms() {
+        let filter = filter(FilterMode::Mask);
+        let extra = ["Apple".to_string()];
+        assert_eq!(filter.masked("an apple", &extra), "an *****");
+        assert_eq!(filter.masked("an apple", &[]), "an apple");
+    }
+
+    #[test]
+    fn rejects_in_reject_mode() {
+        let filter = filter(FilterMode::Reject);
+        assert!(matches!(
+            filter.check("really bad", &[]),
+            Filtered::Rejected
+        ));
+        assert!(matches!(filter.check("fine", &[]), Filtered::Clean));
+        // names can't be rejected, so they're still masked
+        assert_eq!(filter.masked("bad name", &[]), "*** name");
+    }
+}
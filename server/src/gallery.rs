@@ -0,0 +1,109 @@
+use std::io::{Cursor, Write};
+
+use serde::Serialize;
+use uuid::Uuid;
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+use crate::{
+    draw::{CanvasSettings, TimedOp},
+    render::{render_svg, Drawing},
+    room::current_time_millis,
+};
+
+/// How long a gallery is kept after its last drawing was added
+pub const GALLERY_RETENTION_MILLIS: u128 = 60 * 60 * 1000;
+
+/// Drawings of every finished turn of a room's latest game.
+/// Kept by the server after the room itself is removed.
+#[derive(Serialize, Clone, Debug)]
+pub struct Gallery {
+    pub room_id: Uuid,
+    pub title: String,
+    pub updated_at: u128,
+    pub drawings: Vec<Drawing>,
+}
+
+/// A turn's draw operations with their timing, so clients can replay the drawing at its pace
+#[derive(Serialize, Debug)]
+pub struct Timelapse<'a> {
+    pub round: usize,
+    pub word: &'a str,
+    pub drawer_id: Uuid,
+    pub canvas: &'a CanvasSettings,
+    pub duration_ms: u128,
+    pub ops: &'a [TimedOp],
+}
+
+impl Gallery {
+    pub fn new(room_id: Uuid, title: String) -> Gallery {
+        Gallery {
+            room_id,
+            title,
+            updated_at: current_time_millis(),
+            drawings: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, drawings: Vec<Drawing>) {
+        if drawings.is_empty() {
+            return;
+        }
+        self.drawings.extend(drawings);
+        // drawings are rendered in the background, so they can arrive out of order
+        self.drawings.sort_by_key(|drawing| drawing.created_at);
+        self.updated_at = current_time_millis();
+    }
+
+    pub fn timelapse(&self, index: usize) -> Option<Timelapse<'_>> {
+        let drawing = self.drawings.get(index)?;
+        Some(Timelapse {
+            round: drawing.round,
+            word: &drawing.word,
+            drawer_id: drawing.drawer_id,
+            canvas: &drawing.canvas,
+            duration_ms: drawing.duration_ms,
+            ops: &drawing.timeline,
+        })
+    }
+
+    pub fn is_expired(&self, now: u128) -> bool {
+        now.saturating_sub(self.updated_at) > GALLERY_RETENTION_MILLIS
+    }
+
+    /// Zip archive with every drawing as PNG and SVG, and the gallery listing as JSON
+    pub fn to_zip(&self) -> Option<Vec<u8>> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+        zip.start_file("gallery.json", options).ok()?;
+        zip.write_all(serde_json::to_string_pretty(self).ok()?.as_bytes())
+            .ok()?;
+
+        for (index, drawing) in self.drawings.iter().enumerate() {
+            let name = file_name(index, drawing);
+            // PNG is already compressed
+            zip.start_file(
+                format!("{}.png", name),
+                options.compression_method(CompressionMethod::Stored),
+            )
+            .ok()?;
+            zip.write_all(&drawing.png).ok()?;
+
+            if let Some(svg) = render_svg(&drawing.strokes, &drawing.canvas) {
+                zip.start_file(format!("{}.svg", name), options).ok()?;
+                zip.write_all(svg.as_bytes()).ok()?;
+            }
+        }
+        zip.finish().ok().map(Cursor::into_inner)
+    }
+}
+
+/// e.g. `03-round2-apple-tree`
+fn file_name(index: usize, drawing: &Drawing) -> String {
+    let word: String = drawing
+        .word
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '-' })
+        .collect();
+    format!("{:02}-round{}-{}", index + 1, drawing.round, word)
+}
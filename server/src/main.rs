@@ -1,4 +1,4 @@
-use std::str::FromStr;
+use std::{str::FromStr, sync::Arc};
 
 use actix::{Actor, Addr};
 use actix_cors::Cors;
@@ -10,25 +10,32 @@ use actix_web::{
     App, Error, HttpRequest, HttpResponse, HttpServer, Responder,
 };
 use actix_web_actors::ws;
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
 use server::Server;
 use uuid::Uuid;
 
 use crate::{
-    messages::{GetPlayerDetails, RoomDetails},
+    filter::WordFilter,
+    gallery::Gallery,
+    messages::{GetGallery, GetPlayerDetails, RoomDetails},
+    rate_limit::RateLimitConfig,
+    render::render_svg,
     session::UserSession,
 };
 
+mod codec;
+mod commands;
+mod draw;
+mod filter;
+mod gallery;
 mod messages;
+mod rate_limit;
+mod render;
+mod results;
 mod room;
 mod server;
 mod session;
 
-#[derive(Serialize)]
-struct ErrorResponse {
-    msg: String,
-}
-
 #[derive(Debug, Deserialize)]
 pub struct ConnectQuery {
     session: String,
@@ -41,29 +48,21 @@ async fn index(
     stream: web::Payload,
     room_id: Path<String>,
     server: web::Data<Addr<Server>>,
+    limits: web::Data<RateLimitConfig>,
     query: web::Query<ConnectQuery>,
 ) -> Result<HttpResponse, Error> {
     let query = &query.into_inner();
-    let mut session = None;
-    let mut username = None;
-
-    let room_id = Uuid::from_str(&room_id);
+    let session = Uuid::from_str(&query.session).ok();
+    let username = (!query.username.is_empty()).then(|| query.username.clone());
 
-    if let Ok(temp) = Uuid::from_str(&query.session) {
-        session = Some(temp);
-    }
-
-    if query.username.len() > 0 {
-        username = Some(query.username.clone());
-    };
-
-    if room_id.is_ok() {
+    if let Ok(room_id) = Uuid::from_str(&room_id) {
         ws::start(
             UserSession::new(
-                room_id.unwrap(),
+                room_id,
                 server.get_ref().clone(),
                 session,
                 username,
+                *limits.get_ref(),
             ),
             &req,
             stream,
@@ -78,14 +77,12 @@ async fn get_room_details(
     room_id: Path<String>,
     server: web::Data<Addr<Server>>,
 ) -> Result<HttpResponse, Error> {
-    let room_id = Uuid::from_str(&room_id);
-    if room_id.is_ok() {
-        let mut details = String::new();
-        let test = server.get_ref().send(RoomDetails(room_id.unwrap())).await;
-
-        if test.is_ok() {
-            details = test.unwrap();
-        }
+    if let Ok(room_id) = Uuid::from_str(&room_id) {
+        let details = server
+            .get_ref()
+            .send(RoomDetails(room_id))
+            .await
+            .unwrap_or_default();
 
         Ok(HttpResponse::Ok().body(details))
     } else {
@@ -107,21 +104,106 @@ async fn get_player_details(
     let query = query.into_inner();
     let room_id = Uuid::from_str(&query.room_id);
     let user_id = Uuid::from_str(&query.user_id);
-    if room_id.is_ok() && user_id.is_ok() {
+    if let (Ok(room_id), Ok(user_id)) = (room_id, user_id) {
         let details = server
             .get_ref()
-            .send(GetPlayerDetails {
-                user_id: user_id.unwrap(),
-                room_id: room_id.unwrap(),
-            })
+            .send(GetPlayerDetails { user_id, room_id })
             .await;
 
-        Ok(HttpResponse::Ok().body(details.unwrap_or(String::new())))
+        Ok(HttpResponse::Ok().body(details.unwrap_or_default()))
     } else {
         Ok(HttpResponse::BadRequest().body("Invalid room id"))
     }
 }
 
+async fn find_gallery(room_id: &str, server: &Addr<Server>) -> Result<Arc<Gallery>, HttpResponse> {
+    let Ok(room_id) = Uuid::from_str(room_id) else {
+        return Err(HttpResponse::BadRequest().body("Invalid room id"));
+    };
+    match server.send(GetGallery { room_id }).await {
+        Ok(Some(gallery)) => Ok(gallery),
+        _ => Err(HttpResponse::NotFound().body("Gallery not found")),
+    }
+}
+
+#[get("/gallery/{room_id}")]
+async fn get_gallery(
+    room_id: Path<String>,
+    server: web::Data<Addr<Server>>,
+) -> Result<HttpResponse, Error> {
+    match find_gallery(&room_id, server.get_ref()).await {
+        Ok(gallery) => Ok(HttpResponse::Ok().json(&*gallery)),
+        Err(response) => Ok(response),
+    }
+}
+
+#[get("/gallery/{room_id}/zip")]
+async fn get_gallery_zip(
+    room_id: Path<String>,
+    server: web::Data<Addr<Server>>,
+) -> Result<HttpResponse, Error> {
+    let gallery = match find_gallery(&room_id, server.get_ref()).await {
+        Ok(gallery) => gallery,
+        Err(response) => return Ok(response),
+    };
+    // zipping encodes every drawing, so it runs on the blocking pool
+    let room_id = gallery.room_id;
+    match web::block(move || gallery.to_zip()).await? {
+        Some(zip) => Ok(HttpResponse::Ok()
+            .content_type("application/zip")
+            .insert_header((
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"gallery-{}.zip\"", room_id),
+            ))
+            .body(zip)),
+        None => Ok(HttpResponse::InternalServerError().body("Could not create zip")),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DrawingQuery {
+    room_id: String,
+    index: usize,
+    format: String,
+}
+
+/// A single drawing as `png`, `svg` or a `timelapse` of its draw operations
+#[get("/gallery/{room_id}/{index}/{format}")]
+async fn get_drawing(
+    query: Path<DrawingQuery>,
+    server: web::Data<Addr<Server>>,
+) -> Result<HttpResponse, Error> {
+    let query = query.into_inner();
+    let gallery = match find_gallery(&query.room_id, server.get_ref()).await {
+        Ok(gallery) => gallery,
+        Err(response) => return Ok(response),
+    };
+    let drawing_index = query.index;
+    let Some(drawing) = gallery.drawings.get(drawing_index) else {
+        return Ok(HttpResponse::NotFound().body("Drawing not found"));
+    };
+
+    match query.format.as_str() {
+        "png" => Ok(HttpResponse::Ok()
+            .content_type("image/png")
+            .body(drawing.png.clone())),
+        "svg" => {
+            let gallery = gallery.clone();
+            let svg = web::block(move || {
+                let drawing = &gallery.drawings[drawing_index];
+                render_svg(&drawing.strokes, &drawing.canvas)
+            })
+            .await?;
+            match svg {
+                Some(svg) => Ok(HttpResponse::Ok().content_type("image/svg+xml").body(svg)),
+                None => Ok(HttpResponse::InternalServerError().body("Could not render drawing")),
+            }
+        }
+        "timelapse" => Ok(HttpResponse::Ok().json(gallery.timelapse(query.index))),
+        _ => Ok(HttpResponse::BadRequest().body("Unknown format")),
+    }
+}
+
 #[post("/room/new")]
 async fn get_new_room_id() -> impl Responder {
     HttpResponse::Ok().body(Uuid::new_v4().to_string())
@@ -129,7 +211,8 @@ async fn get_new_room_id() -> impl Responder {
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    let server = Server::new().start();
+    let server = Server::new(WordFilter::from_env()).start();
+    let limits = RateLimitConfig::from_env();
 
     HttpServer::new(move || {
         App::new()
@@ -146,7 +229,11 @@ async fn main() -> std::io::Result<()> {
             .service(get_room_details)
             .service(get_new_room_id)
             .service(get_player_details)
+            .service(get_gallery)
+            .service(get_gallery_zip)
+            .service(get_drawing)
             .app_data(web::Data::new(server.clone()))
+            .app_data(web::Data::new(limits))
     })
     .bind(("127.0.0.1", 8080))?
     .run()
@@ -1,8 +1,11 @@
+use std::sync::Arc;
+
 use actix::prelude::*;
+use actix_web::web::Bytes;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::room::GameState;
+use crate::{draw::DrawOp, gallery::Gallery, room::GameState};
 
 // #[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
 // pub enum MessageTypes {
@@ -26,9 +29,10 @@ pub enum MessageTypes {
 
 #[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
 pub enum RelayTypes {
-    Info,
     Draw,
     Text,
+    /// Chat of players who already guessed the word, only sent to the others who know it
+    GuessedText,
 }
 
 #[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
@@ -39,9 +43,33 @@ pub enum GameTypes {
     PlayerLeft,
     Guess,
     GuessResult,
+    GuessRejected,
     EndTurn,
+    TurnResults,
     NewTurn,
     EndGame,
+    KickPlayer,
+    PlayerKicked,
+    Kicked,
+    MutePlayer,
+    Muted,
+    CommandReply,
+    Hint,
+    Whisper,
+    React,
+    Reaction,
+    RateDrawing,
+    DrawingRated,
+    StartVoteKick,
+    VoteKick,
+    VoteKickStarted,
+    VoteKickProgress,
+    VoteKickResult,
+    CanvasReplay,
+    CanvasSettings,
+    ChatHistory,
+    SystemEvent,
+    Error,
 }
 
 #[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
@@ -63,10 +91,16 @@ pub struct ClientServerMessage {
 //     pub content: String,
 // }
 
+/// Binary websocket frame of encoded draw operations, see `codec`
+#[derive(Message, Clone)]
+#[rtype(result = "()")]
+pub struct DrawFrame(pub Bytes);
+
 #[derive(Message)]
 #[rtype(result = "()")]
 pub struct Connect {
     pub addr: Recipient<ClientServerMessage>,
+    pub draw_addr: Recipient<DrawFrame>,
     pub user_id: Uuid,
     pub username: String,
     pub room_id: Uuid,
@@ -87,6 +121,23 @@ pub struct UserMessage {
     pub room_id: Uuid,
 }
 
+/// Draw operations from a binary frame, decoded and rate limited by the session
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct UserDrawFrame {
+    pub user_id: Uuid,
+    pub ops: Vec<DrawOp>,
+    pub room_id: Uuid,
+}
+
+/// Asks the server to send the room's stroke log again
+#[derive(Message, Debug)]
+#[rtype(result = "()")]
+pub struct ResyncCanvas {
+    pub user_id: Uuid,
+    pub room_id: Uuid,
+}
+
 #[derive(Message, Deserialize, Serialize)]
 #[rtype(result = "()")]
 pub struct UserGuessMsg {
@@ -95,6 +146,14 @@ pub struct UserGuessMsg {
     pub content: String,
 }
 
+/// Chat message, received with `Text` and relayed with `Text` or `GuessedText`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChatMsg {
+    pub user_id: Uuid,
+    pub username: String,
+    pub content: String,
+}
+
 #[derive(Message, Deserialize, Serialize)]
 #[rtype(result = "()")]
 pub struct GuessResultMsg {
@@ -104,11 +163,85 @@ pub struct GuessResultMsg {
     pub correct: bool,
 }
 
-#[derive(Message, Serialize, Deserialize)]
-#[rtype(result = "()")]
-pub struct UserMessageData {
-    pub msg_type: String,
+impl GuessResultMsg {
+    /// The same result without the guess text if it was correct, so it doesn't give the word away
+    pub fn redacted(&self) -> GuessResultMsg {
+        GuessResultMsg {
+            user_id: self.user_id,
+            username: self.username.clone(),
+            content: if self.correct {
+                String::new()
+            } else {
+                self.content.clone()
+            },
+            correct: self.correct,
+        }
+    }
+}
+
+/// Something that happened in the room, announced by the server.
+/// Tagged by `event`, fields are only ever added.
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum SystemEvent {
+    PlayerJoined {
+        user_id: Uuid,
+        username: String,
+    },
+    /// `kicked` is set when the owner or a vote removed the player
+    PlayerLeft {
+        user_id: Uuid,
+        username: String,
+        kicked: bool,
+    },
+    PlayerReconnected {
+        user_id: Uuid,
+        username: String,
+    },
+    PlayerGuessed {
+        user_id: Uuid,
+        username: String,
+    },
+    OwnerChanged {
+        owner_id: Uuid,
+        username: String,
+    },
+    TurnStarted {
+        round: usize,
+        total_rounds: usize,
+        drawer_id: Uuid,
+        drawer_name: String,
+    },
+}
+
+/// Sent with `SystemEvent`
+#[derive(Serialize, Debug, Clone)]
+pub struct SystemEventMsg {
+    pub at: u128,
+    #[serde(flatten)]
+    pub event: SystemEvent,
+}
+
+/// A chat message or system event as it was sent, kept for sessions that join later.
+/// A list of these is sent with `ChatHistory` after connecting.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChatEntry {
+    pub msg_type: MessageTypes,
     pub content: String,
+    pub at: u128,
+}
+
+/// Sent with `Error` to the session whose message was refused
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ErrorMsg {
+    pub msg: String,
+}
+
+#[derive(Message, Serialize, Deserialize, Debug, Clone)]
+#[rtype(result = "()")]
+pub struct GuessRejectedMsg {
+    pub reason: String,
+    pub guesses_left: usize,
 }
 
 #[derive(Message, Serialize, Deserialize)]
@@ -122,6 +255,13 @@ pub struct GetPlayerDetails {
     pub room_id: Uuid,
 }
 
+/// Drawings of a room's latest game
+#[derive(Message, Serialize, Deserialize)]
+#[rtype("Option<Arc<Gallery>>")]
+pub struct GetGallery {
+    pub room_id: Uuid,
+}
+
 #[derive(Message, Serialize, Deserialize, Debug, Clone)]
 #[rtype(result = "()")]
 pub struct StartGameMsg {
@@ -136,3 +276,111 @@ pub struct EndGameMsg {
     pub user_id: Uuid,
     pub room_id: Uuid,
 }
+
+#[derive(Message, Serialize, Deserialize, Debug, Clone)]
+#[rtype(result = "()")]
+pub struct KickPlayerMsg {
+    pub target_id: Uuid,
+}
+
+#[derive(Message, Serialize, Deserialize, Debug, Clone)]
+#[rtype(result = "()")]
+pub struct MutePlayerMsg {
+    pub target_id: Uuid,
+}
+
+/// Sent with `CommandReply` to the player who used a chat command
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CommandReplyMsg {
+    pub ok: bool,
+    pub msg: String,
+}
+
+/// Sent with `Hint`, the word with unrevealed letters as `_`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HintMsg {
+    pub hint: String,
+}
+
+/// Sent by a player to whisper to another player of the room
+#[derive(Message, Serialize, Deserialize, Debug, Clone)]
+#[rtype(result = "()")]
+pub struct WhisperMsg {
+    pub target_id: Uuid,
+    pub content: String,
+}
+
+/// Sent with `Whisper` to the recipient, and back to the sender as confirmation
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WhisperedMsg {
+    pub from_id: Uuid,
+    pub from_name: String,
+    pub to_id: Uuid,
+    pub content: String,
+}
+
+/// Sent by a guesser during a turn, `emoji` has to be one of the room's reactions
+#[derive(Message, Serialize, Deserialize, Debug, Clone)]
+#[rtype(result = "()")]
+pub struct ReactMsg {
+    pub emoji: String,
+}
+
+/// Broadcast with `Reaction`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReactionMsg {
+    pub user_id: Uuid,
+    pub emoji: String,
+}
+
+/// Sent after a turn to rate its drawing with a thumbs up or down.
+/// Rating again replaces the earlier rating.
+#[derive(Message, Serialize, Deserialize, Debug, Clone)]
+#[rtype(result = "()")]
+pub struct RateDrawingMsg {
+    pub up: bool,
+}
+
+/// Broadcast with `DrawingRated`, the ratings of the last turn so far
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DrawingRatedMsg {
+    pub round: usize,
+    pub drawer_id: Uuid,
+    pub thumbs_up: usize,
+    pub thumbs_down: usize,
+}
+
+/// Sent with `Muted` to a player whose chat and guesses are being dropped
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MutedMsg {
+    pub reason: String,
+    /// When the mute runs out, or `None` for the rest of the game
+    pub until: Option<u128>,
+}
+
+#[derive(Message, Serialize, Deserialize, Debug, Clone)]
+#[rtype(result = "()")]
+pub struct VoteKickBallotMsg {
+    pub yes: bool,
+}
+
+/// Sent with `VoteKickStarted` and `VoteKickProgress`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VoteKickState {
+    pub target_id: Uuid,
+    pub initiator_id: Uuid,
+    pub yes: usize,
+    pub no: usize,
+    pub required: usize,
+    pub expires_at: u128,
+}
+
+/// Sent with `VoteKickResult`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VoteKickResultMsg {
+    pub target_id: Uuid,
+    pub kicked: bool,
+    pub yes: usize,
+    pub no: usize,
+    pub required: usize,
+}
@@ -0,0 +1,165 @@
+use std::{env, time::Instant};
+
+#[derive(Clone, Copy, Debug)]
+pub struct BucketConfig {
+    /// Most messages (draw operations for `draw`) that can be sent in a burst
+    pub capacity: f64,
+    /// Messages per second allowed once the burst is used up
+    pub refill_per_sec: f64,
+}
+
+impl BucketConfig {
+    /// Reads a "capacity,refill_per_sec" pair from an environment variable
+    fn from_env(key: &str, default: BucketConfig) -> BucketConfig {
+        let Ok(value) = env::var(key) else {
+            return default;
+        };
+        let parsed = value.split_once(',').and_then(|(capacity, refill)| {
+            Some((capacity.trim().parse().ok()?, refill.trim().parse().ok()?))
+        });
+        match parsed {
+            Some((capacity, refill_per_sec)) => BucketConfig {
+                capacity,
+                refill_per_sec,
+            },
+            None => {
+                println!("Invalid value for {}, using default", key);
+                default
+            }
+        }
+    }
+}
+
+/// Per-session limits, shared by every session
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimitConfig {
+    /// Charged per draw operation, whether it comes in a JSON message or a binary frame
+    pub draw: BucketConfig,
+    pub chat: BucketConfig,
+    pub guess: BucketConfig,
+    /// Reactions and drawing ratings
+    pub reaction: BucketConfig,
+    /// Limit hits within `violation_window_secs` before a session is disconnected
+    pub max_violations: u32,
+    pub violation_window_secs: u64,
+    /// Draw relays, binary or JSON, queued for a session before the oldest are dropped.
+    /// The queue is written at a fixed rate, it doesn't track how fast the client reads.
+    pub max_pending_draw_frames: usize,
+}
+
+impl RateLimitConfig {
+    pub fn default() -> RateLimitConfig {
+        RateLimitConfig {
+            draw: BucketConfig {
+                capacity: 240.0,
+                refill_per_sec: 120.0,
+            },
+            chat: BucketConfig {
+                capacity: 5.0,
+                refill_per_sec: 1.0,
+            },
+            guess: BucketConfig {
+                capacity: 5.0,
+                refill_per_sec: 1.0,
+            },
+            reaction: BucketConfig {
+                capacity: 3.0,
+                refill_per_sec: 0.5,
+            },
+            max_violations: 20,
+            violation_window_secs: 10,
+            max_pending_draw_frames: 64,
+        }
+    }
+
+    /// Defaults, overridden by `RATE_LIMIT_DRAW`, `RATE_LIMIT_CHAT`, `RATE_LIMIT_GUESS`
+    /// and `RATE_LIMIT_REACTION`
+    pub fn from_env() -> RateLimitConfig {
+        let default = RateLimitConfig::default();
+        RateLimitConfig {
+            draw: BucketConfig::from_env("RATE_LIMIT_DRAW", default.draw),
+            chat: BucketConfig::from_env("RATE_LIMIT_CHAT", default.chat),
+            guess: BucketConfig::from_env("RATE_LIMIT_GUESS", default.guess),
+            reaction: BucketConfig::from_env("RATE_LIMIT_REACTION", default.reaction),
+            ..default
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct TokenBucket {
+    config: BucketConfig,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(config: BucketConfig) -> TokenBucket {
+        TokenBucket {
+            config,
+            tokens: config.capacity,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Takes a token if one is available
+    pub fn try_take(&mut self) -> bool {
+        self.try_take_n(1)
+    }
+
+    /// Takes `count` tokens if that many are available, otherwise takes none
+    pub fn try_take_n(&mut self, count: usize) -> bool {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens =
+            (self.tokens + elapsed * self.config.refill_per_sec).min(self.config.capacity);
+        self.last_refill = now;
+
+        if self.tokens >= count as f64 {
+            self.tokens -= count as f64;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bucket(capacity: f64) -> TokenBucket {
+        TokenBucket::new(BucketConfig {
+            capacity,
+            refill_per_sec: 0.0,
+        })
+    }
+
+    #[test]
+    fn takes_several_tokens_at_once() {
+        let mut bucket = bucket(10.0);
+        assert!(bucket.try_take_n(4));
+        assert!(bucket.try_take_n(6));
+        assert!(!bucket.try_take());
+        assert!(bucket.try_take_n(0));
+    }
+
+    #[test]
+    fn takes_nothing_when_too_few_are_left() {
+        let mut bucket = bucket(5.0);
+        assert!(!bucket.try_take_n(6));
+        // the failed take left every token in place
+        assert!(bucket.try_take_n(5));
+    }
+
+    #[test]
+    fn refills_over_time() {
+        let mut bucket = TokenBucket::new(BucketConfig {
+            capacity: 2.0,
+            refill_per_sec: 1000.0,
+        });
+        assert!(bucket.try_take_n(2));
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        assert!(bucket.try_take());
+    }
+}
@@ -0,0 +1,434 @@
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+use tiny_skia::{
+    Color, FillRule, LineCap, LineJoin, Paint, Path, PathBuilder, Pixmap, PremultipliedColorU8,
+    Rect, Stroke, Transform,
+};
+use uuid::Uuid;
+
+use crate::draw::{CanvasSettings, DrawOp, TimedOp, REFERENCE_WIDTH};
+
+/// Canvas color, also used by the eraser
+const BACKGROUND: [u8; 3] = [255, 255, 255];
+
+/// Width of rendered images, the height follows the room's aspect ratio
+const RENDER_WIDTH: f64 = REFERENCE_WIDTH;
+
+/// A finished turn's drawing
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Drawing {
+    pub round: usize,
+    pub word: String,
+    pub drawer_id: Uuid,
+    pub canvas: CanvasSettings,
+    pub drawer_name: String,
+    /// Players who guessed the word
+    pub guess_count: usize,
+    pub created_at: u128,
+    /// Empty until `render` has run
+    #[serde(skip)]
+    pub png: Vec<u8>,
+    /// Kept to render other formats on request
+    #[serde(skip)]
+    pub strokes: Vec<DrawOp>,
+    /// Operations as they were drawn, for timelapse replays
+    #[serde(skip)]
+    pub timeline: Vec<TimedOp>,
+    pub duration_ms: u128,
+}
+
+impl Drawing {
+    /// Rasterizes the stroke log into `png`, or returns `None` if it can't be rendered.
+    /// Too slow to run on the server actor.
+    pub fn render(mut self) -> Option<Drawing> {
+        self.png = render_png(&self.strokes, &self.canvas)?;
+        Some(self)
+    }
+}
+
+/// Rasterizes a stroke log the way the client draws it
+pub fn render_png(ops: &[DrawOp], canvas: &CanvasSettings) -> Option<Vec<u8>> {
+    let mut renderer = Renderer::new(canvas, false)?;
+    ops.iter().for_each(|op| renderer.apply(op));
+    renderer.finish_stroke();
+    renderer.pixmap.encode_png().ok()
+}
+
+/// Renders a stroke log as an SVG document.
+/// Flood fills are traced from the raster, as SVG has no equivalent.
+pub fn render_svg(ops: &[DrawOp], canvas: &CanvasSettings) -> Option<String> {
+    let mut renderer = Renderer::new(canvas, true)?;
+    ops.iter().for_each(|op| renderer.apply(op));
+    renderer.finish_stroke();
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+        w = renderer.width,
+        h = renderer.height
+    );
+    svg.push_str(&renderer.svg);
+    svg.push_str("</svg>");
+    Some(svg)
+}
+
+/// Freehand stroke that hasn't ended yet
+struct OpenStroke {
+    path: PathBuilder,
+    points: Vec<(f64, f64)>,
+    rgb: [u8; 3],
+    width: f32,
+}
+
+/// Draws operations onto a pixmap, optionally writing matching SVG elements as it goes
+struct Renderer {
+    pixmap: Pixmap,
+    canvas: CanvasSettings,
+    width: f64,
+    height: f64,
+    stroke: Option<OpenStroke>,
+    svg: String,
+    write_svg: bool,
+}
+
+impl Renderer {
+    fn new(canvas: &CanvasSettings, write_svg: bool) -> Option<Renderer> {
+        let (width, height) = (RENDER_WIDTH, canvas.height_for(RENDER_WIDTH));
+        let mut renderer = Renderer {
+            pixmap: Pixmap::new(width as u32, height as u32)?,
+            canvas: canvas.clone(),
+            width,
+            height,
+            stroke: None,
+            svg: String::new(),
+            write_svg,
+        };
+        renderer.clear();
+        Some(renderer)
+    }
+
+    fn apply(&mut self, op: &DrawOp) {
+        match op {
+            DrawOp::Begin {
+                x,
+                y,
+                color,
+                size,
+                eraser,
+            } => {
+                self.finish_stroke();
+                let (x, y) = self.to_pixels(*x, *y);
+                let mut path = PathBuilder::new();
+                path.move_to(x as f32, y as f32);
+                self.stroke = Some(OpenStroke {
+                    path,
+                    points: vec![(x, y)],
+                    rgb: if *eraser {
+                        BACKGROUND
+                    } else {
+                        self.canvas.rgb(*color)
+                    },
+                    width: self.brush_width(*size),
+                });
+            }
+            DrawOp::Point { x, y } => {
+                let (x, y) = self.to_pixels(*x, *y);
+                if let Some(stroke) = self.stroke.as_mut() {
+                    stroke.path.line_to(x as f32, y as f32);
+                    stroke.points.push((x, y));
+                }
+            }
+            DrawOp::End => self.finish_stroke(),
+            DrawOp::Fill { x, y, color } => {
+                self.finish_stroke();
+                let (x, y) = self.to_pixels(*x, *y);
+                let runs = flood_fill(
+                    &mut self.pixmap,
+                    x as u32,
+                    y as u32,
+                    self.canvas.rgb(*color),
+                    self.write_svg,
+                );
+                if !runs.is_empty() {
+                    let mut d = String::new();
+                    for (y, start, end) in runs {
+                        let _ = write!(d, "M{} {}h{}v1h-{}z", start, y, end - start, end - start);
+                    }
+                    let _ = write!(
+                        self.svg,
+                        r#"<path d="{}" fill="{}"/>"#,
+                        d,
+                        hex(self.canvas.rgb(*color))
+                    );
+                }
+            }
+            DrawOp::Line {
+                x1,
+                y1,
+                x2,
+                y2,
+                color,
+                size,
+            } => {
+                self.finish_stroke();
+                let (x1, y1) = self.to_pixels(*x1, *y1);
+                let (x2, y2) = self.to_pixels(*x2, *y2);
+                let mut path = PathBuilder::new();
+                path.move_to(x1 as f32, y1 as f32);
+                path.line_to(x2 as f32, y2 as f32);
+                self.stroke = Some(OpenStroke {
+                    path,
+                    points: vec![(x1, y1), (x2, y2)],
+                    rgb: self.canvas.rgb(*color),
+                    width: self.brush_width(*size),
+                });
+                self.finish_stroke();
+            }
+            DrawOp::Rect {
+                x1,
+                y1,
+                x2,
+                y2,
+                color,
+                size,
+                filled,
+            }
+            | DrawOp::Ellipse {
+                x1,
+                y1,
+                x2,
+                y2,
+                color,
+                size,
+                filled,
+            } => {
+                self.finish_stroke();
+                let (x1, y1) = self.to_pixels(*x1, *y1);
+                let (x2, y2) = self.to_pixels(*x2, *y2);
+                let (left, top) = (x1.min(x2), y1.min(y2));
+                let (right, bottom) = (x1.max(x2), y1.max(y2));
+                let Some(rect) =
+                    Rect::from_ltrb(left as f32, top as f32, right as f32, bottom as f32)
+                else {
+                    return;
+                };
+                let is_rect = matches!(op, DrawOp::Rect { .. });
+                let path = if is_rect {
+                    Some(PathBuilder::from_rect(rect))
+                } else {
+                    PathBuilder::from_oval(rect)
+                };
+                let Some(path) = path else {
+                    return;
+                };
+                let rgb = self.canvas.rgb(*color);
+                let width = self.brush_width(*size);
+                self.draw_shape(&path, rgb, width, *filled);
+
+                if self.write_svg {
+                    let fill = if *filled {
+                        hex(rgb)
+                    } else {
+                        "none".to_string()
+                    };
+                    let _ = if is_rect {
+                        write!(
+                            self.svg,
+                            r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}" stroke="{}" stroke-width="{}" stroke-linejoin="round"/>"#,
+                            left,
+                            top,
+                            right - left,
+                            bottom - top,
+                            fill,
+                            hex(rgb),
+                            width
+                        )
+                    } else {
+                        write!(
+                            self.svg,
+                            r#"<ellipse cx="{}" cy="{}" rx="{}" ry="{}" fill="{}" stroke="{}" stroke-width="{}"/>"#,
+                            (left + right) / 2.0,
+                            (top + bottom) / 2.0,
+                            (right - left) / 2.0,
+                            (bottom - top) / 2.0,
+                            fill,
+                            hex(rgb),
+                            width
+                        )
+                    };
+                }
+            }
+            DrawOp::Clear => {
+                self.stroke = None;
+                self.clear();
+            }
+            // the stroke log never contains these, undone strokes are removed from it
+            DrawOp::Undo | DrawOp::Redo => (),
+        }
+    }
+
+    fn to_pixels(&self, x: f64, y: f64) -> (f64, f64) {
+        (x * self.width, y * self.height)
+    }
+
+    fn brush_width(&self, size: usize) -> f32 {
+        self.canvas.brush_width(size) * (self.width / REFERENCE_WIDTH) as f32
+    }
+
+    fn clear(&mut self) {
+        self.pixmap.fill(color(BACKGROUND));
+        if self.write_svg {
+            let _ = write!(
+                self.svg,
+                r#"<rect width="100%" height="100%" fill="{}"/>"#,
+                hex(BACKGROUND)
+            );
+        }
+    }
+
+    fn draw_shape(&mut self, path: &Path, rgb: [u8; 3], width: f32, filled: bool) {
+        let paint = paint(rgb);
+        if filled {
+            self.pixmap
+                .fill_path(path, &paint, FillRule::Winding, Transform::identity(), None);
+        }
+        self.pixmap.stroke_path(
+            path,
+            &paint,
+            &line_stroke(width),
+            Transform::identity(),
+            None,
+        );
+    }
+
+    fn finish_stroke(&mut self) {
+        let Some(mut stroke) = self.stroke.take() else {
+            return;
+        };
+        // a stroke with a single point still leaves a dot, like on the client
+        if stroke.points.len() == 1 {
+            let (x, y) = stroke.points[0];
+            stroke.path.line_to(x as f32 + 0.01, y as f32);
+            stroke.points.push((x + 0.01, y));
+        }
+
+        if self.write_svg {
+            let mut d = String::new();
+            for (i, (x, y)) in stroke.points.iter().enumerate() {
+                let _ = write!(d, "{}{} {}", if i == 0 { "M" } else { "L" }, x, y);
+            }
+            let _ = write!(
+                self.svg,
+                r#"<path d="{}" fill="none" stroke="{}" stroke-width="{}" stroke-linecap="round" stroke-linejoin="round"/>"#,
+                d,
+                hex(stroke.rgb),
+                stroke.width
+            );
+        }
+
+        if let Some(path) = stroke.path.finish() {
+            self.pixmap.stroke_path(
+                &path,
+                &paint(stroke.rgb),
+                &line_stroke(stroke.width),
+                Transform::identity(),
+                None,
+            );
+        }
+    }
+}
+
+fn color(rgb: [u8; 3]) -> Color {
+    Color::from_rgba8(rgb[0], rgb[1], rgb[2], 255)
+}
+
+fn hex(rgb: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", rgb[0], rgb[1], rgb[2])
+}
+
+fn paint(rgb: [u8; 3]) -> Paint<'static> {
+    let mut paint = Paint::default();
+    paint.set_color(color(rgb));
+    paint.anti_alias = true;
+    paint
+}
+
+fn line_stroke(width: f32) -> Stroke {
+    Stroke {
+        width,
+        line_cap: LineCap::Round,
+        line_join: LineJoin::Round,
+        ..Stroke::default()
+    }
+}
+
+/// Fills the area of matching color around (x, y).
+/// With `trace` set, returns the filled pixels as (y, start x, end x) runs.
+fn flood_fill(
+    pixmap: &mut Pixmap,
+    x: u32,
+    y: u32,
+    rgb: [u8; 3],
+    trace: bool,
+) -> Vec<(u32, u32, u32)> {
+    let (width, height) = (pixmap.width(), pixmap.height());
+    // the far edges are in range for normalized coordinates
+    let (x, y) = (x.min(width - 1), y.min(height - 1));
+    let Some(fill) = PremultipliedColorU8::from_rgba(rgb[0], rgb[1], rgb[2], 255) else {
+        return Vec::new();
+    };
+    let pixels = pixmap.pixels_mut();
+    let target = pixels[(y * width + x) as usize];
+    if target == fill {
+        return Vec::new();
+    }
+
+    let mut filled = if trace {
+        vec![false; pixels.len()]
+    } else {
+        Vec::new()
+    };
+    let mut stack = vec![(x, y)];
+    while let Some((x, y)) = stack.pop() {
+        let i = (y * width + x) as usize;
+        if pixels[i] != target {
+            continue;
+        }
+        pixels[i] = fill;
+        if trace {
+            filled[i] = true;
+        }
+        if x > 0 {
+            stack.push((x - 1, y));
+        }
+        if x + 1 < width {
+            stack.push((x + 1, y));
+        }
+        if y > 0 {
+            stack.push((x, y - 1));
+        }
+        if y + 1 < height {
+            stack.push((x, y + 1));
+        }
+    }
+
+    let mut runs = Vec::new();
+    if !trace {
+        return runs;
+    }
+    for y in 0..height {
+        let row = &filled[(y * width) as usize..((y + 1) * width) as usize];
+        let mut x = 0;
+        while x < width {
+            if row[x as usize] {
+                let start = x;
+                while x < width && row[x as usize] {
+                    x += 1;
+                }
+                runs.push((y, start, x));
+            } else {
+                x += 1;
+            }
+        }
+    }
+    runs
+}
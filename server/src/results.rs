@@ -0,0 +1,341 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::room::Room;
+
+/// Things that happen during a game, kept by `Room` to build the final results
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum GameEvent {
+    TurnStarted {
+        round: usize,
+        drawer_id: Uuid,
+        at: u128,
+    },
+    CorrectGuess {
+        round: usize,
+        drawer_id: Uuid,
+        user_id: Uuid,
+        elapsed_ms: u128,
+        points: usize,
+    },
+    RoundEnded {
+        round: usize,
+        scores: BTreeMap<Uuid, usize>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GuesserResult {
+    pub user_id: Uuid,
+    pub points: usize,
+    /// Time from the start of the turn to the correct guess
+    pub elapsed_ms: u128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GuessAttempt {
+    pub user_id: Uuid,
+    /// The guess after `normalize_guess`
+    pub text: String,
+    /// Time from the start of the turn to the guess
+    pub offset_ms: u128,
+    pub correct: bool,
+}
+
+/// Score breakdown of a single turn, sent with `TurnResults`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TurnResults {
+    pub round: usize,
+    pub word: String,
+    pub drawer_id: Uuid,
+    /// Points the drawer got for the drawing, from thumbs up while it could be rated
+    pub drawer_points: usize,
+    /// In the order the players guessed
+    pub guessers: Vec<GuesserResult>,
+    /// Filled in when the turn ends
+    pub not_guessed: Vec<Uuid>,
+    /// Every guess made during the turn, in order
+    pub guesses: Vec<GuessAttempt>,
+    /// Points lost to wrong guesses
+    pub penalties: BTreeMap<Uuid, usize>,
+    /// Reactions sent during the turn, by emoji
+    pub reactions: BTreeMap<String, usize>,
+    /// Thumbs up (`true`) or down from each player who rated the drawing
+    pub ratings: BTreeMap<Uuid, bool>,
+}
+
+impl TurnResults {
+    pub fn new(round: usize, word: String, drawer_id: Uuid) -> TurnResults {
+        TurnResults {
+            round,
+            word,
+            drawer_id,
+            drawer_points: 0,
+            guessers: Vec::new(),
+            not_guessed: Vec::new(),
+            guesses: Vec::new(),
+            penalties: BTreeMap::new(),
+            reactions: BTreeMap::new(),
+            ratings: BTreeMap::new(),
+        }
+    }
+
+    pub fn has_guessed(&self, user_id: &Uuid) -> bool {
+        self.guessers
+            .iter()
+            .any(|guesser| &guesser.user_id == user_id)
+    }
+
+    /// Thumbs up and thumbs down given to the drawing
+    pub fn rating_counts(&self) -> (usize, usize) {
+        let up = self.ratings.values().filter(|up| **up).count();
+        (up, self.ratings.len() - up)
+    }
+
+    pub fn guess_count(&self, user_id: &Uuid) -> usize {
+        self.guesses
+            .iter()
+            .filter(|guess| &guess.user_id == user_id)
+            .count()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Standing {
+    pub rank: usize,
+    pub user_id: Uuid,
+    pub username: String,
+    pub score: usize,
+    pub tied: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RoundHistory {
+    pub round: usize,
+    /// Total score of every player at the end of the round
+    pub scores: BTreeMap<Uuid, usize>,
+    /// Points gained during the round
+    pub gained: BTreeMap<Uuid, usize>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum AwardKind {
+    FastestGuesser,
+    BestDrawer,
+    MostCorrectGuesses,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Award {
+    pub kind: AwardKind,
+    /// More than one winner when tied
+    pub user_ids: Vec<Uuid>,
+    /// Milliseconds for `FastestGuesser`, a count otherwise
+    pub value: u128,
+}
+
+/// Sent with `EndGame`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FinalResults {
+    pub room_id: Uuid,
+    pub title: String,
+    pub standings: Vec<Standing>,
+    pub rounds: Vec<RoundHistory>,
+    pub turns: Vec<TurnResults>,
+    pub awards: Vec<Award>,
+}
+
+impl FinalResults {
+    pub fn from_room(room: &Room) -> FinalResults {
+        FinalResults {
+            room_id: room.room_id,
+            title: room.state.title.clone(),
+            standings: standings(room),
+            rounds: round_history(&room.events),
+            turns: room.history.clone(),
+            awards: awards(&room.events),
+        }
+    }
+}
+
+/// Ranks players by score. Tied players share a rank and the next rank is skipped.
+fn standings(room: &Room) -> Vec<Standing> {
+    let mut players: Vec<_> = room.players.iter().collect();
+    players.sort_by_key(|(_, player)| Reverse(player.score));
+
+    let mut standings: Vec<Standing> = Vec::with_capacity(players.len());
+    for (i, (user_id, player)) in players.iter().enumerate() {
+        let rank = match standings.last() {
+            Some(prev) if prev.score == player.score => prev.rank,
+            _ => i + 1,
+        };
+        standings.push(Standing {
+            rank,
+            user_id: **user_id,
+            username: player.username.clone(),
+            score: player.score,
+            tied: false,
+        });
+    }
+
+    let mut ranks: HashMap<usize, usize> = HashMap::new();
+    for standing in &standings {
+        *ranks.entry(standing.rank).or_default() += 1;
+    }
+    for standing in &mut standings {
+        standing.tied = ranks[&standing.rank] > 1;
+    }
+    standings
+}
+
+fn round_history(events: &[GameEvent]) -> Vec<RoundHistory> {
+    let mut history: Vec<RoundHistory> = Vec::new();
+    for event in events {
+        if let GameEvent::RoundEnded { round, scores } = event {
+            let gained = scores
+                .iter()
+                .map(|(id, score)| {
+                    let previous = history
+                        .last()
+                        .and_then(|prev| prev.scores.get(id))
+                        .copied()
+                        .unwrap_or_default();
+                    (*id, score.saturating_sub(previous))
+                })
+                .collect();
+            history.push(RoundHistory {
+                round: *round,
+                scores: scores.clone(),
+                gained,
+            });
+        }
+    }
+    history
+}
+
+fn awards(events: &[GameEvent]) -> Vec<Award> {
+    let mut fastest: HashMap<Uuid, u128> = HashMap::new();
+    let mut guessers_per_drawer: HashMap<Uuid, u128> = HashMap::new();
+    let mut correct_guesses: HashMap<Uuid, u128> = HashMap::new();
+
+    for event in events {
+        if let GameEvent::CorrectGuess {
+            drawer_id,
+            user_id,
+            elapsed_ms,
+            ..
+        } = event
+        {
+            fastest
+                .entry(*user_id)
+                .and_modify(|best| *best = (*best).min(*elapsed_ms))
+                .or_insert(*elapsed_ms);
+            *guessers_per_drawer.entry(*drawer_id).or_default() += 1;
+            *correct_guesses.entry(*user_id).or_default() += 1;
+        }
+    }
+
+    [
+        best_of(AwardKind::FastestGuesser, &fastest, true),
+        best_of(AwardKind::BestDrawer, &guessers_per_drawer, false),
+        best_of(AwardKind::MostCorrectGuesses, &correct_guesses, false),
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+fn best_of(kind: AwardKind, values: &HashMap<Uuid, u128>, lowest: bool) -> Option<Award> {
+    let value = if lowest {
+        *values.values().min()?
+    } else {
+        *values.values().max()?
+    };
+    let mut user_ids: Vec<Uuid> = values
+        .iter()
+        .filter(|(_, v)| **v == value)
+        .map(|(id, _)| *id)
+        .collect();
+    user_ids.sort();
+    Some(Award {
+        kind,
+        user_ids,
+        value,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::room::test_rooms::room_with_players;
+
+    fn room_with_scores(scores: &[usize]) -> (Room, Vec<Uuid>) {
+        let (mut room, ids) = room_with_players(scores.len());
+        for (id, score) in ids.iter().zip(scores) {
+            room.players.get_mut(id).unwrap().score = *score;
+        }
+        (room, ids)
+    }
+
+    fn correct_guess(drawer_id: Uuid, user_id: Uuid, elapsed_ms: u128) -> GameEvent {
+        GameEvent::CorrectGuess {
+            round: 1,
+            drawer_id,
+            user_id,
+            elapsed_ms,
+            points: 100,
+        }
+    }
+
+    #[test]
+    fn standings_share_ranks_when_tied() {
+        let (room, ids) = room_with_scores(&[300, 500, 300, 100]);
+        let standings = standings(&room);
+
+        let ranks: Vec<(Uuid, usize, bool)> = standings
+            .iter()
+            .map(|standing| (standing.user_id, standing.rank, standing.tied))
+            .collect();
+        assert_eq!(ranks[0], (ids[1], 1, false));
+        assert_eq!(ranks[1].1, 2);
+        assert_eq!(ranks[2].1, 2);
+        assert!(ranks[1].2 && ranks[2].2);
+        // the rank after a tie is skipped
+        assert_eq!(ranks[3], (ids[3], 4, false));
+    }
+
+    #[test]
+    fn awards_list_every_tied_winner() {
+        let drawer = Uuid::new_v4();
+        let other_drawer = Uuid::new_v4();
+        let mut guessers = [Uuid::new_v4(), Uuid::new_v4()];
+        guessers.sort();
+        let events = vec![
+            correct_guess(drawer, guessers[0], 4000),
+            correct_guess(drawer, guessers[1], 2000),
+            correct_guess(other_drawer, guessers[0], 2000),
+            correct_guess(other_drawer, guessers[1], 9000),
+        ];
+        let awards = awards(&events);
+        let award = |kind| awards.iter().find(|award| award.kind == kind).unwrap();
+
+        let fastest = award(AwardKind::FastestGuesser);
+        assert_eq!(fastest.user_ids, guessers);
+        assert_eq!(fastest.value, 2000);
+
+        let mut drawers = vec![drawer, other_drawer];
+        drawers.sort();
+        assert_eq!(award(AwardKind::BestDrawer).user_ids, drawers);
+        assert_eq!(award(AwardKind::MostCorrectGuesses).value, 2);
+    }
+
+    #[test]
+    fn no_awards_without_correct_guesses() {
+        assert!(awards(&[]).is_empty());
+    }
+}
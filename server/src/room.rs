@@ -1,16 +1,82 @@
 use std::{
-    collections::BTreeMap,
-    str::FromStr,
-    time::{SystemTime, UNIX_EPOCH},
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::messages::{
-    ClientServerMessage, EndGameMsg, GuessResultMsg, StartGameMsg, UserGuessMsg,
+use crate::{
+    draw::{CanvasSettings, DrawOp, TimedOp},
+    messages::{
+        ChatEntry, DrawingRatedMsg, EndGameMsg, GuessRejectedMsg, GuessResultMsg, MessageTypes,
+        MutedMsg, ReactionMsg, StartGameMsg, UserGuessMsg, VoteKickResultMsg, VoteKickState,
+    },
+    render::Drawing,
+    results::{GameEvent, GuessAttempt, GuesserResult, TurnResults},
 };
 
+/// How long a vote kick stays open before it fails
+pub const VOTE_KICK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long after a turn ends its drawing can be rated.
+/// A finished game's room is kept this long so the last drawing can be rated too.
+pub const RATING_WINDOW: Duration = Duration::from_secs(15);
+
+/// Length of a drawing turn
+const TURN_LENGTH_SECS: u128 = 60;
+
+/// Chat messages and system events kept for players who join or reconnect
+const CHAT_HISTORY_SIZE: usize = 50;
+
+/// Extra blocked words a room can set
+const MAX_BLOCKED_TERMS: usize = 200;
+
+/// How long a player is muted for repeating themselves or flooding the chat
+const AUTO_MUTE_MILLIS: u128 = 30_000;
+
+/// Identical messages in a row that get a player muted
+const MAX_REPEATED_MESSAGES: usize = 3;
+
+/// Messages within `FLOOD_WINDOW_MILLIS` that get a player muted
+const FLOOD_MESSAGES: usize = 8;
+const FLOOD_WINDOW_MILLIS: u128 = 10_000;
+
+/// Most rounds a game can be changed to
+const MAX_ROUNDS: usize = 20;
+
+/// Emoji guessers can react to a drawing with
+const REACTIONS: [&str; 6] = ["👍", "👎", "😂", "😮", "🔥", "❤️"];
+
+/// Most operations kept in the stroke log, new strokes are refused past this
+const MAX_STROKE_OPS: usize = 10_000;
+
+/// Most flood fills kept in the stroke log, each one costs a pass over the image when rendering
+const MAX_FILL_OPS: usize = 50;
+
+/// Most operations recorded for a turn's timelapse, drawing stops for the turn past this
+const MAX_TIMELINE_OPS: usize = 20_000;
+
+/// Minimum number of active players needed before a vote kick can be started
+const MIN_VOTE_KICK_PLAYERS: usize = 3;
+
+/// Lowercases a guess and collapses its whitespace so guesses compare loosely
+pub fn normalize_guess(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+pub fn current_time_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis()
+}
+
+// serialized as is, the client checks for "STARTED"
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum GameStatus {
     WAITING,
@@ -26,6 +92,23 @@ pub struct GameState {
     pub title: String,
     pub correct_word: String,
     pub round_start_time: u128,
+    /// Guesses each player may make per turn, unlimited when `None`
+    #[serde(default)]
+    pub max_guesses: Option<usize>,
+    /// Points taken away for every wrong guess, for the hardcore variant
+    #[serde(default)]
+    pub wrong_guess_penalty: usize,
+    /// Points the drawer gets for every thumbs up on their drawing
+    #[serde(default)]
+    pub rating_bonus: usize,
+    /// Stops players from whispering to each other
+    #[serde(default)]
+    pub disable_whispers: bool,
+    #[serde(default = "CanvasSettings::default")]
+    pub canvas: CanvasSettings,
+    /// Words blocked in this room on top of the server's list, not sent back to clients
+    #[serde(default, skip_serializing)]
+    pub blocked_terms: Vec<String>,
 }
 
 impl GameState {
@@ -40,6 +123,12 @@ impl GameState {
                 .duration_since(UNIX_EPOCH)
                 .unwrap()
                 .as_millis(),
+            max_guesses: None,
+            wrong_guess_penalty: 0,
+            rating_bonus: 0,
+            disable_whispers: false,
+            canvas: CanvasSettings::default(),
+            blocked_terms: Vec::new(),
         }
     }
 }
@@ -48,8 +137,12 @@ impl GameState {
 pub struct Player {
     pub username: String,
     pub score: usize,
-    pub prev_score: usize,
     pub active: bool,
+    /// Ratings the player's drawings got
+    #[serde(default)]
+    pub thumbs_up: usize,
+    #[serde(default)]
+    pub thumbs_down: usize,
 }
 
 impl Player {
@@ -57,12 +150,47 @@ impl Player {
         Player {
             username,
             score: 0,
-            prev_score: 0,
             active: true,
+            thumbs_up: 0,
+            thumbs_down: 0,
         }
     }
 }
 
+pub enum ChatChannel {
+    /// Relayed to the whole room
+    Everyone,
+    /// Relayed only to players who know the word, so it can't give the answer away
+    Guessed,
+    /// Players still guessing during a turn, who send guesses rather than chat
+    Guess,
+}
+
+pub enum GuessOutcome {
+    /// Broadcast to the room
+    Result(GuessResultMsg),
+    /// Sent back to the guesser only
+    Rejected(GuessRejectedMsg),
+    Ignored,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct VoteKick {
+    pub id: Uuid,
+    pub target_id: Uuid,
+    pub initiator_id: Uuid,
+    pub votes: HashMap<Uuid, bool>,
+    pub expires_at: u128,
+}
+
+/// A player's recent chat messages and guesses, for spotting spam
+#[derive(Clone, Debug, Default)]
+pub struct ChatActivity {
+    last_message: String,
+    repeats: usize,
+    sent_at: VecDeque<u128>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Room {
     pub room_id: Uuid,
@@ -70,6 +198,43 @@ pub struct Room {
     pub players: BTreeMap<Uuid, Player>,
     pub owner: Uuid,
     pub state: GameState,
+    #[serde(skip)]
+    pub vote_kick: Option<VoteKick>,
+    #[serde(skip)]
+    pub banned: HashSet<Uuid>,
+    /// Positions of the word's letters revealed by hints this turn
+    #[serde(skip)]
+    pub hint_letters: Vec<usize>,
+    /// Muted players by session id, so mutes outlast reconnects
+    #[serde(skip)]
+    pub mutes: HashMap<Uuid, MutedMsg>,
+    #[serde(skip)]
+    pub chat_activity: HashMap<Uuid, ChatActivity>,
+    #[serde(skip)]
+    pub events: Vec<GameEvent>,
+    #[serde(skip)]
+    pub turn: Option<TurnResults>,
+    /// Until when the last finished turn's drawing can be rated
+    #[serde(skip)]
+    pub rating_closes_at: u128,
+    /// Finished turns of the current game
+    #[serde(skip)]
+    pub history: Vec<TurnResults>,
+    /// Draw operations of the current turn since the last clear, in order
+    #[serde(skip)]
+    pub strokes: Vec<DrawOp>,
+    /// Strokes taken off the log by `Undo`, most recent last
+    #[serde(skip)]
+    pub redo_strokes: Vec<Vec<DrawOp>>,
+    /// Every relayed draw operation of the current turn, including undone ones
+    #[serde(skip)]
+    pub timeline: Vec<TimedOp>,
+    /// Rendered drawings of finished turns, until the server moves them to the room's gallery
+    #[serde(skip)]
+    pub drawings: Vec<Drawing>,
+    /// Recent chat and system events, oldest first
+    #[serde(skip)]
+    pub chat_history: VecDeque<ChatEntry>,
 }
 
 impl Room {
@@ -82,19 +247,40 @@ impl Room {
             players,
             owner,
             state: GameState::default(),
+            vote_kick: None,
+            banned: HashSet::new(),
+            hint_letters: Vec::new(),
+            mutes: HashMap::new(),
+            chat_activity: HashMap::new(),
+            events: Vec::new(),
+            turn: None,
+            rating_closes_at: 0,
+            history: Vec::new(),
+            strokes: Vec::new(),
+            redo_strokes: Vec::new(),
+            timeline: Vec::new(),
+            drawings: Vec::new(),
+            chat_history: VecDeque::new(),
         }
     }
 
     pub fn start_game(&mut self, msg: StartGameMsg) -> Option<String> {
         if self.owner == msg.user_id {
             self.status = GameStatus::STARTED;
-            self.state = msg.state;
+            let mut state = msg.state;
+            state.canvas = state.canvas.validated();
+            state.blocked_terms.truncate(MAX_BLOCKED_TERMS);
+            self.state = state;
 
             self.state.correct_word = "default".to_string();
-            self.state.round_start_time = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_millis();
+
+            self.events.clear();
+            self.history.clear();
+            self.drawings.clear();
+            // owner mutes last until the next game, automatic ones run out on their own
+            self.mutes.retain(|_, mute| mute.until.is_some());
+            self.chat_activity.clear();
+            self.start_turn();
 
             let self_clone = self.clone();
             Some(serde_json::to_string(&self_clone).unwrap())
@@ -103,48 +289,1117 @@ impl Room {
         }
     }
 
-    pub fn validate_guess(&mut self, data: UserGuessMsg) -> Option<GuessResultMsg> {
+    pub fn validate_guess(&mut self, data: UserGuessMsg) -> GuessOutcome {
+        let Some(turn) = self.turn.as_ref() else {
+            return GuessOutcome::Ignored;
+        };
+        // the drawer and players who already guessed would only be scoring the answer
+        if self.knows_word(&data.user_id) {
+            return GuessOutcome::Ignored;
+        }
+        // the name is the room's, not whatever the client put in the message
+        let Some(username) = self.players.get(&data.user_id).map(|p| p.username.clone()) else {
+            return GuessOutcome::Ignored;
+        };
+        let guesses_made = turn.guess_count(&data.user_id);
+        if let Some(max_guesses) = self.state.max_guesses {
+            if guesses_made >= max_guesses {
+                return GuessOutcome::Rejected(GuessRejectedMsg {
+                    reason: format!("You have used all {} guesses for this turn", max_guesses),
+                    guesses_left: 0,
+                });
+            }
+        }
+
         let mut content = GuessResultMsg {
             user_id: data.user_id,
-            username: data.username,
+            username,
             content: data.content.clone(),
             correct: false,
         };
-        if self.state.correct_word == data.content.trim() {
+        let text = normalize_guess(&data.content);
+        let elapsed_time = current_time_millis().saturating_sub(self.state.round_start_time);
+        let correct = text == normalize_guess(&self.state.correct_word);
+        if let Some(turn) = self.turn.as_mut() {
+            turn.guesses.push(GuessAttempt {
+                user_id: data.user_id,
+                text,
+                offset_ms: elapsed_time,
+                correct,
+            });
+        }
+
+        if correct {
             content.correct = true;
-            let current_time = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_millis();
-            let elapsed_time = current_time - self.state.round_start_time;
+            let points = (TURN_LENGTH_SECS.saturating_sub(elapsed_time / 1000) * 100
+                / TURN_LENGTH_SECS) as usize;
+
+            if let Some(player) = self.players.get_mut(&data.user_id) {
+                player.score += points;
+            }
+
+            let Some(turn) = self.turn.as_mut() else {
+                return GuessOutcome::Ignored;
+            };
+            turn.guessers.push(GuesserResult {
+                user_id: data.user_id,
+                points,
+                elapsed_ms: elapsed_time,
+            });
+            let drawer_id = turn.drawer_id;
 
+            self.events.push(GameEvent::CorrectGuess {
+                round: self.state.current_round,
+                drawer_id,
+                user_id: data.user_id,
+                elapsed_ms: elapsed_time,
+                points,
+            });
+        } else if self.state.wrong_guess_penalty > 0 {
+            let penalty = self.state.wrong_guess_penalty;
             if let Some(player) = self.players.get_mut(&data.user_id) {
-                player.prev_score = player.score;
-                player.score += ((60 - (elapsed_time / 1000)) * 100 / 60) as usize;
+                let deducted = player.score.min(penalty);
+                player.score -= deducted;
+                if let Some(turn) = self.turn.as_mut() {
+                    *turn.penalties.entry(data.user_id).or_default() += deducted;
+                }
             }
-            println!("{:?}", self);
         }
-        Some(content)
+        GuessOutcome::Result(content)
     }
 
-    pub fn end_turn(&mut self, data: EndGameMsg) -> (Option<Self>, bool) {
+    /// Ends the current turn if `data.user_id` is drawing.
+    /// Returns the room, the finished turn's results and whether the game is over.
+    pub fn end_turn(&mut self, data: EndGameMsg) -> (Option<Self>, Option<TurnResults>, bool) {
+        // a late end turn after the last one mustn't end the game a second time
+        if !matches!(self.status, GameStatus::STARTED) || self.turn.is_none() {
+            return (Some(self.clone()), None, false);
+        }
         let mut game_ended = false;
+        let mut turn_results = None;
         if let Some(currently_drawing) = self.players.iter().nth(self.state.currently_drawing) {
             if currently_drawing.0 == &data.user_id {
+                turn_results = self.finish_turn();
                 if self.state.currently_drawing + 1 < self.players.len() {
                     self.state.currently_drawing += 1;
+                    self.start_turn();
                 } else {
-                    if self.state.current_round + 1 <= self.state.total_rounds {
+                    self.log_round_ended();
+                    if self.state.current_round < self.state.total_rounds {
                         self.state.current_round += 1;
                         self.state.currently_drawing = 0;
+                        self.start_turn();
                         println!("CHANGE ROUND");
                     } else {
                         println!("END GAME");
+                        self.status = GameStatus::OVER;
                         game_ended = true;
                     }
                 }
             }
         }
-        (Some(self.clone()), game_ended)
+        (Some(self.clone()), turn_results, game_ended)
+    }
+
+    /// The player at the current drawing position
+    pub fn drawer_id(&self) -> Option<Uuid> {
+        self.players
+            .keys()
+            .nth(self.state.currently_drawing)
+            .copied()
+    }
+
+    /// Whether `user_id` is the drawer of a turn that is in progress
+    pub fn can_draw(&self, user_id: &Uuid) -> bool {
+        matches!(self.status, GameStatus::STARTED)
+            && self.turn.is_some()
+            && self.drawer_id().as_ref() == Some(user_id)
+    }
+
+    /// Finds a player by username, ignoring case
+    pub fn find_player(&self, username: &str) -> Option<Uuid> {
+        self.players
+            .iter()
+            .find(|(_, player)| player.username.eq_ignore_ascii_case(username))
+            .map(|(id, _)| *id)
+    }
+
+    /// Reveals another letter of the word, keeping at least one hidden.
+    /// Returns the word with unrevealed letters as `_`, or `None` if there is nothing to reveal.
+    pub fn reveal_hint(&mut self) -> Option<String> {
+        self.turn.as_ref()?;
+        let word: Vec<char> = self.state.correct_word.chars().collect();
+        let hidden: Vec<usize> = (0..word.len())
+            .filter(|i| word[*i].is_alphanumeric() && !self.hint_letters.contains(i))
+            .collect();
+        if hidden.len() <= 1 {
+            return None;
+        }
+        let pick = hidden[current_time_millis() as usize % hidden.len()];
+        self.hint_letters.push(pick);
+
+        let hint = word
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                if !c.is_alphanumeric() || self.hint_letters.contains(&i) {
+                    c.to_string()
+                } else {
+                    "_".to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        Some(hint)
+    }
+
+    /// Changes the number of rounds, as long as the current round is still part of the game
+    pub fn set_total_rounds(&mut self, rounds: usize) -> Result<(), String> {
+        let min = self.state.current_round.max(1);
+        if !(min..=MAX_ROUNDS).contains(&rounds) {
+            return Err(format!("Rounds must be between {} and {}", min, MAX_ROUNDS));
+        }
+        self.state.total_rounds = rounds;
+        Ok(())
+    }
+
+    /// Counts a guesser's reaction to the drawing of the turn in progress
+    pub fn react(&mut self, user_id: &Uuid, emoji: &str) -> Option<ReactionMsg> {
+        if !REACTIONS.contains(&emoji) || !self.is_active_player(user_id) {
+            return None;
+        }
+        let turn = self.turn.as_mut()?;
+        if &turn.drawer_id == user_id {
+            return None;
+        }
+        *turn.reactions.entry(emoji.to_string()).or_default() += 1;
+        Some(ReactionMsg {
+            user_id: *user_id,
+            emoji: emoji.to_string(),
+        })
+    }
+
+    /// Rates the drawing of the last finished turn, while its results are shown.
+    /// Adds to the drawer's stats, and to their score if the room gives a bonus for a thumbs up.
+    pub fn rate_drawing(&mut self, user_id: &Uuid, up: bool) -> Option<DrawingRatedMsg> {
+        if current_time_millis() > self.rating_closes_at || !self.is_active_player(user_id) {
+            return None;
+        }
+        let turn = self.history.last_mut()?;
+        if &turn.drawer_id == user_id {
+            return None;
+        }
+        let previous = turn.ratings.insert(*user_id, up);
+        if previous == Some(up) {
+            return None;
+        }
+        let (thumbs_up, thumbs_down) = turn.rating_counts();
+        let msg = DrawingRatedMsg {
+            round: turn.round,
+            drawer_id: turn.drawer_id,
+            thumbs_up,
+            thumbs_down,
+        };
+
+        let bonus = self.state.rating_bonus;
+        // the turn record keeps the same points as the drawer's score
+        if previous == Some(true) {
+            turn.drawer_points = turn.drawer_points.saturating_sub(bonus);
+        }
+        if up {
+            turn.drawer_points += bonus;
+        }
+        if let Some(drawer) = self.players.get_mut(&msg.drawer_id) {
+            match previous {
+                Some(true) => {
+                    drawer.thumbs_up = drawer.thumbs_up.saturating_sub(1);
+                    drawer.score = drawer.score.saturating_sub(bonus);
+                }
+                Some(false) => drawer.thumbs_down = drawer.thumbs_down.saturating_sub(1),
+                None => (),
+            }
+            if up {
+                drawer.thumbs_up += 1;
+                drawer.score += bonus;
+            } else {
+                drawer.thumbs_down += 1;
+            }
+        }
+        Some(msg)
+    }
+
+    /// Whether the game is over and its last drawing can no longer be rated
+    pub fn is_finished(&self) -> bool {
+        matches!(self.status, GameStatus::OVER) && current_time_millis() >= self.rating_closes_at
+    }
+
+    /// Keeps a chat message or system event in the history, dropping the oldest when full
+    pub fn log_chat(&mut self, msg_type: MessageTypes, content: String) {
+        if self.chat_history.len() >= CHAT_HISTORY_SIZE {
+            self.chat_history.pop_front();
+        }
+        self.chat_history.push_back(ChatEntry {
+            msg_type,
+            content,
+            at: current_time_millis(),
+        });
+    }
+
+    /// The player's mute, if they have one that hasn't run out
+    pub fn active_mute(&mut self, user_id: &Uuid) -> Option<MutedMsg> {
+        let expired = self
+            .mutes
+            .get(user_id)?
+            .until
+            .is_some_and(|until| until <= current_time_millis());
+        if expired {
+            self.mutes.remove(user_id);
+            return None;
+        }
+        self.mutes.get(user_id).cloned()
+    }
+
+    /// Mutes a player for the rest of the game, if asked by the owner
+    pub fn owner_mute(&mut self, user_id: &Uuid, target_id: &Uuid) -> Option<MutedMsg> {
+        if self.owner != *user_id || user_id == target_id || !self.players.contains_key(target_id) {
+            return None;
+        }
+        let mute = MutedMsg {
+            reason: "You were muted by the room owner".to_string(),
+            until: None,
+        };
+        self.mutes.insert(*target_id, mute.clone());
+        Some(mute)
+    }
+
+    /// Records a chat message or guess, muting the player for a while if they keep
+    /// repeating themselves or flood the chat.
+    /// Guesses are only checked against mutes, as they have their own rate and guess limits
+    /// and retrying a close guess isn't spam.
+    /// Returns the mute that stops this message, if there is one.
+    pub fn check_spam(&mut self, user_id: &Uuid, text: &str, guess: bool) -> Option<MutedMsg> {
+        if let Some(mute) = self.active_mute(user_id) {
+            return Some(mute);
+        }
+        if guess {
+            return None;
+        }
+
+        let now = current_time_millis();
+        let text = normalize_guess(text);
+        let activity = self.chat_activity.entry(*user_id).or_default();
+        if activity.last_message == text {
+            activity.repeats += 1;
+        } else {
+            activity.last_message = text;
+            activity.repeats = 1;
+        }
+        activity.sent_at.push_back(now);
+        while activity
+            .sent_at
+            .front()
+            .is_some_and(|at| now.saturating_sub(*at) > FLOOD_WINDOW_MILLIS)
+        {
+            activity.sent_at.pop_front();
+        }
+
+        let reason = if activity.repeats >= MAX_REPEATED_MESSAGES {
+            "You were muted for repeating the same message"
+        } else if activity.sent_at.len() >= FLOOD_MESSAGES {
+            "You were muted for sending too many messages"
+        } else {
+            return None;
+        };
+        self.chat_activity.remove(user_id);
+        let mute = MutedMsg {
+            reason: reason.to_string(),
+            until: Some(now + AUTO_MUTE_MILLIS),
+        };
+        self.mutes.insert(*user_id, mute.clone());
+        Some(mute)
+    }
+
+    /// Where a player's chat message goes in the current turn state
+    pub fn chat_channel(&self, user_id: &Uuid) -> ChatChannel {
+        let Some(turn) = self.turn.as_ref() else {
+            return ChatChannel::Everyone;
+        };
+        // the drawer knows the word too, so they could type it to everyone
+        if turn.has_guessed(user_id) || turn.drawer_id == *user_id {
+            ChatChannel::Guessed
+        } else {
+            ChatChannel::Guess
+        }
+    }
+
+    /// Checks that `user_id` may whisper to `target_id`.
+    /// During a turn, only players who both know the word or both don't can whisper,
+    /// so the drawer and those who guessed can't pass it on.
+    pub fn check_whisper(&self, user_id: &Uuid, target_id: &Uuid) -> Result<(), String> {
+        if self.state.disable_whispers {
+            return Err("Whispers are disabled in this room".to_string());
+        }
+        if user_id == target_id || !self.is_active_player(target_id) {
+            return Err("That player can't be whispered to".to_string());
+        }
+        if self.knows_word(user_id) != self.knows_word(target_id) {
+            return Err("You can't whisper to that player during this turn".to_string());
+        }
+        Ok(())
+    }
+
+    /// Whether `user_id` knows the current turn's word, as its drawer or by guessing it
+    pub fn knows_word(&self, user_id: &Uuid) -> bool {
+        self.turn
+            .as_ref()
+            .is_some_and(|turn| turn.drawer_id == *user_id || turn.has_guessed(user_id))
+    }
+
+    /// Whether the last operation in the stroke log left a stroke open
+    fn stroke_open(&self) -> bool {
+        matches!(
+            self.strokes.last(),
+            Some(DrawOp::Begin { .. }) | Some(DrawOp::Point { .. })
+        )
+    }
+
+    fn fill_count(&self) -> usize {
+        self.strokes
+            .iter()
+            .filter(|op| matches!(op, DrawOp::Fill { .. }))
+            .count()
+    }
+
+    /// Applies a draw operation to the stroke log.
+    /// Returns the operations to relay, or `None` if the operation doesn't fit the current stroke
+    /// or the turn's drawing is full.
+    pub fn record_draw(&mut self, op: DrawOp) -> Option<Vec<DrawOp>> {
+        if self.timeline.len() >= MAX_TIMELINE_OPS {
+            return None;
+        }
+        let full = self.strokes.len() >= MAX_STROKE_OPS;
+        let ops = match op {
+            DrawOp::Clear => {
+                self.strokes.clear();
+                self.redo_strokes.clear();
+                Some(vec![op])
+            }
+            DrawOp::Undo => {
+                if self.stroke_open() {
+                    return None;
+                }
+                let start = self.strokes.iter().rposition(DrawOp::starts_stroke)?;
+                let stroke = self.strokes.split_off(start);
+                self.redo_strokes.push(stroke);
+                Some(vec![op])
+            }
+            DrawOp::Redo => {
+                if self.stroke_open() {
+                    return None;
+                }
+                let stroke = self.redo_strokes.pop()?;
+                self.strokes.extend(stroke.iter().cloned());
+                Some(stroke)
+            }
+            DrawOp::Begin { .. }
+            | DrawOp::Fill { .. }
+            | DrawOp::Line { .. }
+            | DrawOp::Rect { .. }
+            | DrawOp::Ellipse { .. } => {
+                if full || (matches!(op, DrawOp::Fill { .. }) && self.fill_count() >= MAX_FILL_OPS)
+                {
+                    return None;
+                }
+                self.redo_strokes.clear();
+                let mut ops = Vec::new();
+                if self.stroke_open() {
+                    self.strokes.push(DrawOp::End);
+                    ops.push(DrawOp::End);
+                }
+                self.strokes.push(op.clone());
+                ops.push(op);
+                Some(ops)
+            }
+            // an open stroke can always be ended, even once the log is full
+            DrawOp::Point { .. } | DrawOp::End => {
+                if !self.stroke_open() || (full && op != DrawOp::End) {
+                    return None;
+                }
+                self.strokes.push(op.clone());
+                Some(vec![op])
+            }
+        }?;
+
+        let offset_ms = current_time_millis().saturating_sub(self.state.round_start_time);
+        self.timeline.extend(ops.iter().map(|op| TimedOp {
+            offset_ms,
+            op: op.clone(),
+        }));
+        Some(ops)
+    }
+
+    fn start_turn(&mut self) {
+        self.state.round_start_time = current_time_millis();
+        self.strokes.clear();
+        self.redo_strokes.clear();
+        self.timeline.clear();
+        self.hint_letters.clear();
+        self.turn = None;
+        if let Some(drawer_id) = self.drawer_id() {
+            self.turn = Some(TurnResults::new(
+                self.state.current_round,
+                self.state.correct_word.clone(),
+                drawer_id,
+            ));
+            self.events.push(GameEvent::TurnStarted {
+                round: self.state.current_round,
+                drawer_id,
+                at: self.state.round_start_time,
+            });
+        }
+    }
+
+    fn finish_turn(&mut self) -> Option<TurnResults> {
+        let mut turn = self.turn.take()?;
+        turn.not_guessed = self
+            .players
+            .keys()
+            .filter(|id| **id != turn.drawer_id && !turn.has_guessed(id))
+            .copied()
+            .collect();
+        self.history.push(turn.clone());
+        self.rating_closes_at = current_time_millis() + RATING_WINDOW.as_millis();
+
+        self.drawings.push(Drawing {
+            round: turn.round,
+            word: turn.word.clone(),
+            drawer_id: turn.drawer_id,
+            canvas: self.state.canvas.clone(),
+            drawer_name: self
+                .players
+                .get(&turn.drawer_id)
+                .map(|player| player.username.clone())
+                .unwrap_or_default(),
+            guess_count: turn.guessers.len(),
+            created_at: current_time_millis(),
+            png: Vec::new(),
+            strokes: self.strokes.clone(),
+            timeline: std::mem::take(&mut self.timeline),
+            duration_ms: current_time_millis().saturating_sub(self.state.round_start_time),
+        });
+        Some(turn)
+    }
+
+    fn log_round_ended(&mut self) {
+        let scores = self
+            .players
+            .iter()
+            .map(|(id, player)| (*id, player.score))
+            .collect();
+        self.events.push(GameEvent::RoundEnded {
+            round: self.state.current_round,
+            scores,
+        });
+    }
+
+    /// Removes a player from the room and bans them from rejoining.
+    /// Keeps the drawing order and ownership consistent.
+    pub fn remove_player(&mut self, user_id: &Uuid) -> Option<Player> {
+        let index = self.players.keys().position(|id| id == user_id)?;
+        let player = self.players.remove(user_id)?;
+        self.banned.insert(*user_id);
+
+        if index < self.state.currently_drawing {
+            self.state.currently_drawing -= 1;
+        }
+        if self.state.currently_drawing >= self.players.len() {
+            self.state.currently_drawing = 0;
+        }
+
+        if &self.owner == user_id {
+            let next_owner = self
+                .players
+                .iter()
+                .find(|(_, player)| player.active)
+                .or_else(|| self.players.iter().next());
+            if let Some((id, _)) = next_owner {
+                self.owner = *id;
+            }
+        }
+
+        if self
+            .vote_kick
+            .as_ref()
+            .is_some_and(|vote| &vote.target_id == user_id)
+        {
+            self.vote_kick = None;
+        }
+
+        Some(player)
+    }
+
+    fn is_active_player(&self, user_id: &Uuid) -> bool {
+        self.players
+            .get(user_id)
+            .is_some_and(|player| player.active)
+    }
+
+    /// Yes votes needed for the current vote kick to pass
+    fn vote_kick_required(&self, target_id: &Uuid) -> usize {
+        let eligible = self
+            .players
+            .iter()
+            .filter(|(id, player)| player.active && *id != target_id)
+            .count();
+        eligible / 2 + 1
+    }
+
+    fn vote_kick_state(&self) -> Option<VoteKickState> {
+        let vote = self.vote_kick.as_ref()?;
+        let yes = vote.votes.values().filter(|yes| **yes).count();
+        Some(VoteKickState {
+            target_id: vote.target_id,
+            initiator_id: vote.initiator_id,
+            yes,
+            no: vote.votes.len() - yes,
+            required: self.vote_kick_required(&vote.target_id),
+            expires_at: vote.expires_at,
+        })
+    }
+
+    pub fn start_vote_kick(
+        &mut self,
+        initiator_id: Uuid,
+        target_id: Uuid,
+    ) -> Option<VoteKickState> {
+        if self.vote_kick.is_some()
+            || initiator_id == target_id
+            || !self.is_active_player(&initiator_id)
+            || !self.players.contains_key(&target_id)
+        {
+            return None;
+        }
+
+        let active_players = self.players.values().filter(|p| p.active).count();
+        if active_players < MIN_VOTE_KICK_PLAYERS {
+            return None;
+        }
+
+        let mut votes = HashMap::new();
+        votes.insert(initiator_id, true);
+        self.vote_kick = Some(VoteKick {
+            id: Uuid::new_v4(),
+            target_id,
+            initiator_id,
+            votes,
+            expires_at: current_time_millis() + VOTE_KICK_TIMEOUT.as_millis(),
+        });
+        self.vote_kick_state()
+    }
+
+    pub fn cast_vote_kick(&mut self, voter_id: Uuid, yes: bool) -> Option<VoteKickState> {
+        let active = self.is_active_player(&voter_id);
+        let vote = self.vote_kick.as_mut()?;
+        if !active || vote.target_id == voter_id {
+            return None;
+        }
+        vote.votes.insert(voter_id, yes);
+        self.vote_kick_state()
+    }
+
+    /// Returns `Some(true)` once the vote has passed and `Some(false)` once it can no longer pass
+    pub fn vote_kick_outcome(&self) -> Option<bool> {
+        let state = self.vote_kick_state()?;
+        let eligible = self
+            .players
+            .iter()
+            .filter(|(id, player)| player.active && **id != state.target_id)
+            .count();
+        if state.yes >= state.required {
+            Some(true)
+        } else if eligible.saturating_sub(state.no) < state.required {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    /// Closes the current vote kick. The caller is responsible for removing the player.
+    pub fn finish_vote_kick(&mut self, kicked: bool) -> Option<VoteKickResultMsg> {
+        let state = self.vote_kick_state()?;
+        self.vote_kick = None;
+        Some(VoteKickResultMsg {
+            target_id: state.target_id,
+            kicked,
+            yes: state.yes,
+            no: state.no,
+            required: state.required,
+        })
+    }
+}
+
+/// Rooms for the unit tests of every module
+#[cfg(test)]
+pub mod test_rooms {
+    use super::*;
+
+    /// A waiting room with `count` players, returned with their ids in the room's order
+    pub fn room_with_players(count: usize) -> (Room, Vec<Uuid>) {
+        let owner = Uuid::new_v4();
+        let mut room = Room::new(owner, Uuid::new_v4(), "owner".to_string());
+        for i in 1..count {
+            room.players
+                .insert(Uuid::new_v4(), Player::default(format!("player{}", i)));
+        }
+        let ids = room.players.keys().copied().collect();
+        (room, ids)
+    }
+
+    /// A room whose game has started, with the first of the ids drawing
+    pub fn started_room(count: usize) -> (Room, Vec<Uuid>) {
+        let (mut room, ids) = room_with_players(count);
+        let msg = StartGameMsg {
+            user_id: room.owner,
+            room_id: room.room_id,
+            state: GameState::default(),
+        };
+        room.start_game(msg);
+        (room, ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_rooms::*;
+    use super::*;
+
+    #[test]
+    fn vote_kick_needs_min_players() {
+        let (mut room, ids) = room_with_players(MIN_VOTE_KICK_PLAYERS - 1);
+        assert!(room.start_vote_kick(ids[0], ids[1]).is_none());
+
+        let (mut room, ids) = room_with_players(MIN_VOTE_KICK_PLAYERS);
+        assert!(room.start_vote_kick(ids[0], ids[1]).is_some());
+    }
+
+    #[test]
+    fn vote_kick_ignores_self_and_inactive_players() {
+        let (mut room, ids) = room_with_players(4);
+        assert!(room.start_vote_kick(ids[0], ids[0]).is_none());
+
+        room.players.get_mut(&ids[2]).unwrap().active = false;
+        assert!(room.start_vote_kick(ids[2], ids[1]).is_none());
+
+        room.start_vote_kick(ids[0], ids[1]).unwrap();
+        assert!(room.cast_vote_kick(ids[1], false).is_none());
+        assert!(room.cast_vote_kick(ids[2], true).is_none());
+    }
+
+    #[test]
+    fn vote_kick_passes_with_majority() {
+        let (mut room, ids) = room_with_players(5);
+        let state = room.start_vote_kick(ids[0], ids[4]).unwrap();
+        // four players besides the target, so three yes votes are needed
+        assert_eq!(state.required, 3);
+        assert_eq!(room.vote_kick_outcome(), None);
+
+        room.cast_vote_kick(ids[1], true);
+        assert_eq!(room.vote_kick_outcome(), None);
+        room.cast_vote_kick(ids[2], true);
+        assert_eq!(room.vote_kick_outcome(), Some(true));
+
+        let result = room.finish_vote_kick(true).unwrap();
+        assert!(result.kicked);
+        assert_eq!(result.yes, 3);
+        assert!(room.vote_kick.is_none());
+    }
+
+    #[test]
+    fn vote_kick_fails_once_majority_is_out_of_reach() {
+        let (mut room, ids) = room_with_players(5);
+        room.start_vote_kick(ids[0], ids[4]).unwrap();
+        room.cast_vote_kick(ids[1], false);
+        assert_eq!(room.vote_kick_outcome(), None);
+        room.cast_vote_kick(ids[2], false);
+        assert_eq!(room.vote_kick_outcome(), Some(false));
+    }
+
+    fn begin(x: f64) -> DrawOp {
+        DrawOp::Begin {
+            x,
+            y: 0.5,
+            color: 0,
+            size: 0,
+            eraser: false,
+        }
+    }
+
+    fn draw_stroke(room: &mut Room, x: f64) {
+        room.record_draw(begin(x)).unwrap();
+        room.record_draw(DrawOp::Point { x, y: 0.6 }).unwrap();
+        room.record_draw(DrawOp::End).unwrap();
+    }
+
+    #[test]
+    fn undo_and_redo_move_whole_strokes() {
+        let (mut room, _) = room_with_players(1);
+        draw_stroke(&mut room, 0.1);
+        draw_stroke(&mut room, 0.2);
+        let drawn = room.strokes.clone();
+
+        assert_eq!(room.record_draw(DrawOp::Undo), Some(vec![DrawOp::Undo]));
+        assert_eq!(room.strokes, drawn[..3]);
+
+        let redone = room.record_draw(DrawOp::Redo).unwrap();
+        assert_eq!(redone, drawn[3..]);
+        assert_eq!(room.strokes, drawn);
+        assert_eq!(room.record_draw(DrawOp::Redo), None);
+    }
+
+    #[test]
+    fn undo_is_refused_while_a_stroke_is_open() {
+        let (mut room, _) = room_with_players(1);
+        assert_eq!(room.record_draw(DrawOp::Undo), None);
+
+        room.record_draw(begin(0.1)).unwrap();
+        assert_eq!(room.record_draw(DrawOp::Undo), None);
+        assert_eq!(room.record_draw(DrawOp::Redo), None);
+    }
+
+    #[test]
+    fn new_strokes_clear_the_redo_stack() {
+        let (mut room, _) = room_with_players(1);
+        draw_stroke(&mut room, 0.1);
+        room.record_draw(DrawOp::Undo).unwrap();
+        draw_stroke(&mut room, 0.2);
+        assert_eq!(room.record_draw(DrawOp::Redo), None);
+
+        room.record_draw(DrawOp::Undo).unwrap();
+        room.record_draw(DrawOp::Clear).unwrap();
+        assert!(room.strokes.is_empty());
+        assert_eq!(room.record_draw(DrawOp::Redo), None);
+    }
+
+    #[test]
+    fn a_new_stroke_closes_the_open_one() {
+        let (mut room, _) = room_with_players(1);
+        room.record_draw(begin(0.1)).unwrap();
+        let ops = room.record_draw(begin(0.2)).unwrap();
+        assert_eq!(ops, vec![DrawOp::End, begin(0.2)]);
+        assert_eq!(room.record_draw(DrawOp::End), Some(vec![DrawOp::End]));
+        assert_eq!(room.record_draw(DrawOp::End), None);
+    }
+
+    #[test]
+    fn drawing_stops_once_the_stroke_log_is_full() {
+        let (mut room, _) = room_with_players(1);
+        room.record_draw(begin(0.1)).unwrap();
+        while room.strokes.len() < MAX_STROKE_OPS {
+            room.record_draw(DrawOp::Point { x: 0.2, y: 0.2 }).unwrap();
+        }
+        assert_eq!(room.record_draw(DrawOp::Point { x: 0.3, y: 0.3 }), None);
+        assert_eq!(room.record_draw(DrawOp::End), Some(vec![DrawOp::End]));
+        assert_eq!(room.record_draw(begin(0.4)), None);
+
+        // clearing frees the stroke log, but not the turn's timeline
+        room.record_draw(DrawOp::Clear).unwrap();
+        draw_stroke(&mut room, 0.5);
+        room.timeline
+            .resize(MAX_TIMELINE_OPS, room.timeline[0].clone());
+        assert_eq!(room.record_draw(DrawOp::Clear), None);
+    }
+
+    #[test]
+    fn fills_are_capped() {
+        let (mut room, _) = room_with_players(1);
+        let fill = DrawOp::Fill {
+            x: 0.5,
+            y: 0.5,
+            color: 0,
+        };
+        for _ in 0..MAX_FILL_OPS {
+            room.record_draw(fill.clone()).unwrap();
+        }
+        assert_eq!(room.record_draw(fill.clone()), None);
+        room.record_draw(DrawOp::Undo).unwrap();
+        assert!(room.record_draw(fill).is_some());
+    }
+
+    fn guess(room: &Room, user_id: Uuid, content: &str) -> UserGuessMsg {
+        UserGuessMsg {
+            user_id,
+            username: room.players[&user_id].username.clone(),
+            content: content.to_string(),
+        }
+    }
+
+    #[test]
+    fn only_players_still_guessing_can_guess() {
+        let (mut room, ids) = started_room(3);
+        let word = room.state.correct_word.clone();
+        assert!(matches!(room.chat_channel(&ids[0]), ChatChannel::Guessed));
+        assert!(matches!(room.chat_channel(&ids[1]), ChatChannel::Guess));
+
+        let drawer_guess = guess(&room, ids[0], &word);
+        assert!(matches!(
+            room.validate_guess(drawer_guess),
+            GuessOutcome::Ignored
+        ));
+
+        let correct = guess(&room, ids[1], &word);
+        assert!(matches!(
+            room.validate_guess(correct),
+            GuessOutcome::Result(_)
+        ));
+        assert!(matches!(room.chat_channel(&ids[1]), ChatChannel::Guessed));
+        let again = guess(&room, ids[1], &word);
+        assert!(matches!(room.validate_guess(again), GuessOutcome::Ignored));
+    }
+
+    #[test]
+    fn guesses_are_rejected_after_the_limit() {
+        let (mut room, ids) = started_room(2);
+        room.state.max_guesses = Some(2);
+        for _ in 0..2 {
+            let wrong = guess(&room, ids[1], "wrong");
+            assert!(matches!(
+                room.validate_guess(wrong),
+                GuessOutcome::Result(_)
+            ));
+        }
+        let word = room.state.correct_word.clone();
+        let GuessOutcome::Rejected(rejected) = room.validate_guess(guess(&room, ids[1], &word))
+        else {
+            panic!("guess over the limit was not rejected");
+        };
+        assert_eq!(rejected.guesses_left, 0);
+        assert!(!room.knows_word(&ids[1]));
+        assert_eq!(room.turn.as_ref().unwrap().guess_count(&ids[1]), 2);
+    }
+
+    #[test]
+    fn wrong_guesses_cost_at_most_the_score() {
+        let (mut room, ids) = started_room(2);
+        room.state.wrong_guess_penalty = 30;
+        room.players.get_mut(&ids[1]).unwrap().score = 50;
+
+        room.validate_guess(guess(&room, ids[1], "wrong"));
+        assert_eq!(room.players[&ids[1]].score, 20);
+        room.validate_guess(guess(&room, ids[1], "still wrong"));
+        assert_eq!(room.players[&ids[1]].score, 0);
+        room.validate_guess(guess(&room, ids[1], "wrong again"));
+        assert_eq!(room.players[&ids[1]].score, 0);
+
+        let penalties = &room.turn.as_ref().unwrap().penalties;
+        assert_eq!(penalties[&ids[1]], 50);
+    }
+
+    #[test]
+    fn guess_results_use_the_room_username() {
+        let (mut room, ids) = started_room(2);
+        let mut spoofed = guess(&room, ids[1], "wrong");
+        spoofed.username = "owner".to_string();
+        let GuessOutcome::Result(result) = room.validate_guess(spoofed) else {
+            panic!("guess was not broadcast");
+        };
+        assert_eq!(result.username, room.players[&ids[1]].username);
+    }
+
+    #[test]
+    fn repeating_a_message_mutes() {
+        let (mut room, ids) = room_with_players(1);
+        for _ in 1..MAX_REPEATED_MESSAGES {
+            assert!(room.check_spam(&ids[0], "Hello", false).is_none());
+        }
+        // repeats are compared after normalizing
+        let mute = room.check_spam(&ids[0], "  hello ", false).unwrap();
+        assert!(mute.until.is_some());
+        assert!(room.check_spam(&ids[0], "something else", false).is_some());
+    }
+
+    #[test]
+    fn flooding_the_chat_mutes() {
+        let (mut room, ids) = room_with_players(2);
+        for i in 1..FLOOD_MESSAGES {
+            assert!(room
+                .check_spam(&ids[0], &format!("message {}", i), false)
+                .is_none());
+        }
+        assert!(room.check_spam(&ids[0], "one more", false).is_some());
+        // other players aren't affected
+        assert!(room.check_spam(&ids[1], "one more", false).is_none());
+    }
+
+    #[test]
+    fn guesses_only_check_mutes() {
+        let (mut room, ids) = room_with_players(2);
+        let owner = room.owner;
+        let player = if ids[0] == owner { ids[1] } else { ids[0] };
+        for _ in 0..FLOOD_MESSAGES {
+            assert!(room.check_spam(&player, "apple", true).is_none());
+        }
+        assert!(room.check_spam(&player, "hello", false).is_none());
+
+        room.owner_mute(&owner, &player).unwrap();
+        assert!(room.check_spam(&player, "apple", true).is_some());
+    }
+
+    #[test]
+    fn starting_a_game_ends_owner_mutes() {
+        let (mut room, ids) = room_with_players(2);
+        let owner = room.owner;
+        let target = if ids[0] == owner { ids[1] } else { ids[0] };
+        room.owner_mute(&owner, &target).unwrap();
+        room.check_spam(&owner, "hello", false);
+        room.check_spam(&owner, "hello", false);
+
+        room.start_game(StartGameMsg {
+            user_id: owner,
+            room_id: room.room_id,
+            state: GameState::default(),
+        });
+        assert!(room.mutes.is_empty());
+        assert!(room.chat_activity.is_empty());
+    }
+
+    #[test]
+    fn mutes_run_out() {
+        let (mut room, ids) = room_with_players(1);
+        room.mutes.insert(
+            ids[0],
+            MutedMsg {
+                reason: String::new(),
+                until: Some(current_time_millis() - 1),
+            },
+        );
+        assert!(room.check_spam(&ids[0], "hi", false).is_none());
+        assert!(room.mutes.is_empty());
+    }
+
+    #[test]
+    fn only_the_owner_can_mute() {
+        let (mut room, ids) = room_with_players(3);
+        let owner = room.owner;
+        let others: Vec<Uuid> = ids.into_iter().filter(|id| *id != owner).collect();
+        assert!(room.owner_mute(&others[0], &others[1]).is_none());
+        assert!(room.owner_mute(&owner, &owner).is_none());
+
+        let mute = room.owner_mute(&owner, &others[0]).unwrap();
+        assert!(mute.until.is_none());
+        assert!(room.check_spam(&others[0], "hi", false).is_some());
+    }
+
+    #[test]
+    fn removing_the_drawer_after_their_turn_keeps_the_next_drawer() {
+        let (mut room, ids) = started_room(3);
+        let drawer = room.drawer_id().unwrap();
+        assert_eq!(drawer, ids[0]);
+
+        room.end_turn(EndGameMsg {
+            user_id: drawer,
+            room_id: room.room_id,
+        });
+        room.remove_player(&drawer);
+
+        assert_eq!(room.drawer_id(), Some(ids[1]));
+        assert_eq!(room.turn.as_ref().map(|turn| turn.drawer_id), Some(ids[1]));
+    }
+
+    /// Plays a one round game to its end, leaving its last drawing, by the last player, to rate
+    fn finished_game(count: usize) -> (Room, Vec<Uuid>) {
+        let (mut room, ids) = started_room(count);
+        room.state.total_rounds = 1;
+        room.state.rating_bonus = 10;
+        for _ in 0..count {
+            let drawer = room.drawer_id().unwrap();
+            room.end_turn(EndGameMsg {
+                user_id: drawer,
+                room_id: room.room_id,
+            });
+        }
+        assert!(matches!(room.status, GameStatus::OVER));
+        (room, ids)
+    }
+
+    #[test]
+    fn ending_a_finished_game_again_does_nothing() {
+        let (mut room, ids) = finished_game(2);
+        let turns = room.history.len();
+        let (_, results, game_ended) = room.end_turn(EndGameMsg {
+            user_id: ids[1],
+            room_id: room.room_id,
+        });
+        assert!(results.is_none());
+        assert!(!game_ended);
+        assert_eq!(room.history.len(), turns);
+    }
+
+    #[test]
+    fn changing_a_rating_moves_the_bonus() {
+        let (mut room, ids) = finished_game(2);
+        let drawer = ids[1];
+        room.rate_drawing(&ids[0], true).unwrap();
+        assert_eq!(room.players[&drawer].score, 10);
+        assert_eq!(room.history.last().unwrap().drawer_points, 10);
+
+        let rated = room.rate_drawing(&ids[0], false).unwrap();
+        assert_eq!((rated.thumbs_up, rated.thumbs_down), (0, 1));
+        let player = &room.players[&drawer];
+        assert_eq!((player.thumbs_up, player.thumbs_down), (0, 1));
+        assert_eq!(player.score, 0);
+        assert_eq!(room.history.last().unwrap().drawer_points, 0);
+
+        // the same rating again changes nothing
+        assert!(room.rate_drawing(&ids[0], false).is_none());
+    }
+
+    #[test]
+    fn drawers_cant_rate_their_own_drawing() {
+        let (mut room, ids) = finished_game(2);
+        assert!(room.rate_drawing(&ids[1], true).is_none());
+        assert_eq!(room.players[&ids[1]].thumbs_up, 0);
+    }
+
+    #[test]
+    fn ratings_close_with_the_window() {
+        let (mut room, ids) = finished_game(2);
+        room.rating_closes_at = current_time_millis() - 1;
+        assert!(room.rate_drawing(&ids[0], true).is_none());
+        assert!(room.is_finished());
+    }
+
+    #[test]
+    fn whispers_stay_between_players_who_know_the_same() {
+        let (mut room, ids) = started_room(4);
+        let word = room.state.correct_word.clone();
+        let (drawer, guessed, guessing, other) = (ids[0], ids[1], ids[2], ids[3]);
+        room.validate_guess(guess(&room, guessed, &word));
+
+        // the drawer and players who guessed know the word, the rest don't
+        assert!(room.check_whisper(&drawer, &guessed).is_ok());
+        assert!(room.check_whisper(&guessed, &drawer).is_ok());
+        assert!(room.check_whisper(&guessing, &other).is_ok());
+        assert!(room.check_whisper(&drawer, &guessing).is_err());
+        assert!(room.check_whisper(&guessed, &guessing).is_err());
+        assert!(room.check_whisper(&guessing, &guessed).is_err());
+
+        assert!(room.check_whisper(&guessing, &guessing).is_err());
+        assert!(room.check_whisper(&guessing, &Uuid::new_v4()).is_err());
+    }
+
+    #[test]
+    fn anyone_can_whisper_between_turns() {
+        let (room, ids) = room_with_players(2);
+        assert!(room.check_whisper(&ids[0], &ids[1]).is_ok());
+    }
+
+    #[test]
+    fn whispers_can_be_disabled() {
+        let (mut room, ids) = room_with_players(2);
+        room.state.disable_whispers = true;
+        assert_eq!(
+            room.check_whisper(&ids[0], &ids[1]),
+            Err("Whispers are disabled in this room".to_string())
+        );
     }
 }
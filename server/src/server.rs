@@ -1,35 +1,57 @@
-use std::{
-    collections::{BTreeMap, HashMap},
-    str::FromStr,
-};
+use std::{collections::HashMap, str::FromStr, sync::Arc, time::Duration};
 
 use actix::prelude::*;
+use actix_web::rt::task;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::{
+    codec::encode_ops,
+    commands::{self, Command},
+    draw::DrawOp,
+    filter::{Filtered, WordFilter},
+    gallery::Gallery,
     messages::*,
-    room::{self, GameState, GameStatus, Player, Room},
+    render::Drawing,
+    results::{FinalResults, TurnResults},
+    room::{
+        current_time_millis, ChatChannel, GuessOutcome, Player, Room, RATING_WINDOW,
+        VOTE_KICK_TIMEOUT,
+    },
 };
 
 pub struct Server {
     sessions: HashMap<Uuid, Recipient<ClientServerMessage>>,
+    draw_sessions: HashMap<Uuid, Recipient<DrawFrame>>,
     rooms: HashMap<Uuid, Room>,
+    /// Drawings of each room's latest game, kept after the room is removed.
+    /// Shared with the HTTP handlers, which get a snapshot instead of a copy.
+    galleries: HashMap<Uuid, Arc<Gallery>>,
+    filter: WordFilter,
 }
 
+/// How often expired galleries are removed
+const GALLERY_CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+
 impl Actor for Server {
     type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(GALLERY_CLEANUP_INTERVAL, |act, _ctx| {
+            let now = current_time_millis();
+            act.galleries.retain(|_, gallery| !gallery.is_expired(now));
+        });
+    }
 }
 
 impl Server {
-    pub fn new() -> Self {
-        let test_room = Room {
-            room_id: Uuid::from_str("67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap(),
-            status: room::GameStatus::WAITING,
-            players: BTreeMap::new(),
-            owner: Uuid::new_v4(),
-            state: GameState::default(),
-        };
+    pub fn new(filter: WordFilter) -> Self {
+        let mut test_room = Room::new(
+            Uuid::new_v4(),
+            Uuid::from_str("67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap(),
+            String::new(),
+        );
+        test_room.players.clear();
 
         let mut rooms = HashMap::new();
 
@@ -39,7 +61,10 @@ impl Server {
         );
         Server {
             sessions: HashMap::new(),
+            draw_sessions: HashMap::new(),
             rooms,
+            galleries: HashMap::new(),
+            filter,
         }
     }
 
@@ -50,13 +75,15 @@ impl Server {
         content: String,
         skip_id: Option<&Uuid>,
     ) {
-        if let Some(room) = self.rooms.get(&room) {
+        if let Some(room) = self.rooms.get(room) {
+            let guessed_only = msg_type == MessageTypes::Relay(RelayTypes::GuessedText);
             let msg = ClientServerMessage { msg_type, content };
-            for (id, _) in &room.players {
-                if skip_id.is_some() && id == skip_id.unwrap() {
+            for id in room.players.keys() {
+                let skipped = skip_id == Some(id);
+                if skipped || (guessed_only && !room.knows_word(id)) {
                     continue;
                 } else {
-                    if let Some(addr) = self.sessions.get(&id) {
+                    if let Some(addr) = self.sessions.get(id) {
                         addr.do_send(msg.clone())
                     }
                 }
@@ -64,13 +91,100 @@ impl Server {
         }
     }
 
+    /// Broadcasts a chat message or system event and keeps it in the room's chat history
+    pub fn send_logged(
+        &mut self,
+        room_id: &Uuid,
+        msg_type: MessageTypes,
+        content: String,
+        skip_id: Option<&Uuid>,
+    ) {
+        if let Some(room) = self.rooms.get_mut(room_id) {
+            room.log_chat(msg_type, content.clone());
+        }
+        self.send_message(room_id, msg_type, content, skip_id);
+    }
+
+    /// Announces a system event to the room and keeps it in the chat history
+    pub fn send_event(&mut self, room_id: &Uuid, event: SystemEvent, skip_id: Option<&Uuid>) {
+        let msg = SystemEventMsg {
+            at: current_time_millis(),
+            event,
+        };
+        self.send_logged(
+            room_id,
+            MessageTypes::Game(GameTypes::SystemEvent),
+            serde_json::to_string(&msg).unwrap(),
+            skip_id,
+        );
+    }
+
+    /// Announces the turn the room is on, if one is in progress
+    fn send_turn_started(&mut self, room_id: &Uuid) {
+        let Some(room) = self.rooms.get(room_id) else {
+            return;
+        };
+        let Some(turn) = room.turn.as_ref() else {
+            return;
+        };
+        let event = SystemEvent::TurnStarted {
+            round: turn.round,
+            total_rounds: room.state.total_rounds,
+            drawer_id: turn.drawer_id,
+            drawer_name: self.username(room_id, &turn.drawer_id),
+        };
+        self.send_event(room_id, event, None);
+    }
+
+    fn username(&self, room_id: &Uuid, user_id: &Uuid) -> String {
+        self.get_player_details(*user_id, *room_id)
+            .map(|player| player.username.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn send_draw_frame(&self, room: &Uuid, data: Vec<u8>, skip_id: Option<&Uuid>) {
+        if let Some(room) = self.rooms.get(room) {
+            let frame = DrawFrame(data.into());
+            for id in room.players.keys() {
+                if skip_id == Some(id) {
+                    continue;
+                }
+                if let Some(addr) = self.draw_sessions.get(id) {
+                    addr.do_send(frame.clone())
+                }
+            }
+        }
+    }
+
+    /// Sends the room's stroke log to a single session
+    pub fn send_canvas_replay(&self, room_id: Uuid, user_id: Uuid) {
+        if let Some(room) = self.rooms.get(&room_id) {
+            self.send_user_message(
+                MessageTypes::Game(GameTypes::CanvasReplay),
+                serde_json::to_string(&room.strokes).unwrap_or_default(),
+                &user_id,
+            );
+        }
+    }
+
     pub fn send_user_message(&self, msg_type: MessageTypes, content: String, user_id: &Uuid) {
-        if let Some(session) = self.sessions.get(&user_id) {
+        if let Some(session) = self.sessions.get(user_id) {
             let msg = ClientServerMessage { msg_type, content };
             session.do_send(msg);
         }
     }
 
+    fn send_blocked_error(&self, user_id: &Uuid) {
+        self.send_user_message(
+            MessageTypes::Game(GameTypes::Error),
+            serde_json::to_string(&ErrorMsg {
+                msg: "Your message contains blocked words".to_string(),
+            })
+            .unwrap(),
+            user_id,
+        );
+    }
+
     pub fn get_room_details(&self, room_id: Uuid) -> Option<&Room> {
         if let Some(room) = self.rooms.get(&room_id) {
             Some(room)
@@ -98,14 +212,15 @@ impl Server {
                 player.active = true;
                 new_player = player.clone();
             } else {
-                new_player.username = username;
+                new_player.username = self.filter.masked(&username, &room.state.blocked_terms);
                 room.players.insert(user_id, new_player.clone());
             }
         } else {
+            let username = self.filter.masked(&username, &[]);
             self.rooms
                 .insert(room_id, Room::new(user_id, room_id, username));
         }
-        return new_player;
+        new_player
     }
 
     // pub fn start_game(&mut self, msg: StartGameMsg) {
@@ -133,67 +248,723 @@ impl Server {
     //     }
     // }
 
-    pub fn start_game(&mut self, msg: StartGameMsg) {
+    pub fn start_game(&mut self, mut msg: StartGameMsg) {
+        msg.state.title = self
+            .filter
+            .masked(&msg.state.title, &msg.state.blocked_terms);
         if let Some(room) = self.rooms.get_mut(&msg.room_id) {
             let data = room.start_game(msg.clone());
             if let Some(content) = data {
+                self.galleries.insert(
+                    msg.room_id,
+                    Arc::new(Gallery::new(msg.room_id, room.state.title.clone())),
+                );
                 self.send_message(
                     &msg.room_id,
                     MessageTypes::Game(GameTypes::NewTurn),
                     content,
                     None,
-                )
+                );
+                self.send_turn_started(&msg.room_id);
             }
         }
     }
 
-    pub fn validate_guess(&mut self, msg: ClientServerMessage, room_id: Uuid) {
-        let data = serde_json::from_str::<UserGuessMsg>(&msg.content);
-        if data.is_err() {
+    pub fn validate_guess(&mut self, msg: ClientServerMessage, user_id: Uuid, room_id: Uuid) {
+        let Ok(mut data) = serde_json::from_str::<UserGuessMsg>(&msg.content) else {
+            return;
+        };
+        // limits and penalties apply to the sender, whatever id the message claims
+        data.user_id = user_id;
+        if self.allow_chat(&data.content, true, user_id, room_id) {
+            self.submit_guess(data, room_id);
+        }
+    }
+
+    /// Checks a chat message or guess against the sender's mute and the spam limits,
+    /// telling them privately when it is dropped
+    fn allow_chat(&mut self, text: &str, guess: bool, user_id: Uuid, room_id: Uuid) -> bool {
+        let Some(room) = self.rooms.get_mut(&room_id) else {
+            return false;
+        };
+        let Some(mute) = room.check_spam(&user_id, text, guess) else {
+            return true;
+        };
+        self.send_user_message(
+            MessageTypes::Game(GameTypes::Muted),
+            serde_json::to_string(&mute).unwrap(),
+            &user_id,
+        );
+        false
+    }
+
+    /// Sends a guess result to the room, leaving out a correct guess for players who
+    /// don't know the word yet
+    fn send_guess_result(&self, room_id: &Uuid, content: &GuessResultMsg) {
+        let Some(room) = self.rooms.get(room_id) else {
             return;
+        };
+        let full = serde_json::to_string(content).unwrap();
+        let redacted = serde_json::to_string(&content.redacted()).unwrap();
+        for id in room.players.keys() {
+            let content = if room.knows_word(id) {
+                full.clone()
+            } else {
+                redacted.clone()
+            };
+            self.send_user_message(MessageTypes::Game(GameTypes::GuessResult), content, id);
         }
-        let data = data.unwrap();
+    }
+
+    fn submit_guess(&mut self, mut data: UserGuessMsg, room_id: Uuid) {
         if let Some(room) = self.rooms.get_mut(&room_id) {
-            let content = room.validate_guess(data);
+            let user_id = data.user_id;
+            match self.filter.check(&data.content, &room.state.blocked_terms) {
+                Filtered::Clean => (),
+                Filtered::Masked(masked) => data.content = masked,
+                Filtered::Rejected => {
+                    self.send_blocked_error(&user_id);
+                    return;
+                }
+            }
+            match room.validate_guess(data) {
+                GuessOutcome::Result(content) => {
+                    room.log_chat(
+                        MessageTypes::Game(GameTypes::GuessResult),
+                        serde_json::to_string(&content.redacted()).unwrap(),
+                    );
+                    self.send_guess_result(&room_id, &content);
+                    if content.correct {
+                        let event = SystemEvent::PlayerGuessed {
+                            user_id: content.user_id,
+                            username: content.username,
+                        };
+                        self.send_event(&room_id, event, None);
+                    }
+                }
+                GuessOutcome::Rejected(content) => self.send_user_message(
+                    MessageTypes::Game(GameTypes::GuessRejected),
+                    serde_json::to_string(&content).unwrap(),
+                    &user_id,
+                ),
+                GuessOutcome::Ignored => (),
+            }
+        }
+    }
+
+    /// Takes a guess from a player who is still guessing, or relays it as chat once they
+    /// know the word, as their client may not have caught up with the turn yet
+    pub fn guess_or_chat(&mut self, msg: ClientServerMessage, user_id: Uuid, room_id: Uuid) {
+        let guessing = self
+            .rooms
+            .get(&room_id)
+            .is_some_and(|room| matches!(room.chat_channel(&user_id), ChatChannel::Guess));
+        if guessing {
+            self.validate_guess(msg, user_id, room_id);
+        } else {
+            self.send_chat(msg, user_id, room_id);
+        }
+    }
+
+    /// Relays a chat message to the players who may see it.
+    /// Players still guessing send guesses instead, so they're held to the guess rate limit
+    pub fn send_chat(&mut self, msg: ClientServerMessage, user_id: Uuid, room_id: Uuid) {
+        let Ok(data) = serde_json::from_str::<ChatMsg>(&msg.content) else {
+            return;
+        };
+        if !self.allow_chat(&data.content, false, user_id, room_id) {
+            return;
+        }
+        let Some(room) = self.rooms.get(&room_id) else {
+            return;
+        };
+        let Some(player) = room.players.get(&user_id) else {
+            return;
+        };
+        let relay_type = match room.chat_channel(&user_id) {
+            ChatChannel::Everyone => RelayTypes::Text,
+            ChatChannel::Guessed => RelayTypes::GuessedText,
+            ChatChannel::Guess => {
+                self.send_user_message(
+                    MessageTypes::Game(GameTypes::Error),
+                    serde_json::to_string(&ErrorMsg {
+                        msg: "You are still guessing, send that again as a guess".to_string(),
+                    })
+                    .unwrap(),
+                    &user_id,
+                );
+                return;
+            }
+        };
+        let content = match self.filter.check(&data.content, &room.state.blocked_terms) {
+            Filtered::Clean => data.content,
+            Filtered::Masked(masked) => masked,
+            Filtered::Rejected => {
+                self.send_blocked_error(&user_id);
+                return;
+            }
+        };
+        let content = ChatMsg {
+            user_id,
+            username: player.username.clone(),
+            content,
+        };
+        let content = serde_json::to_string(&content).unwrap();
+        // chat from players who know the word isn't kept, the history goes to everyone who joins
+        if relay_type == RelayTypes::Text {
+            self.send_logged(&room_id, MessageTypes::Relay(relay_type), content, None);
+        } else {
+            self.send_message(&room_id, MessageTypes::Relay(relay_type), content, None);
+        }
+    }
+
+    /// Ends the sender's turn, if they are drawing
+    pub fn end_turn(&mut self, user_id: Uuid, room_id: Uuid, ctx: &mut Context<Self>) {
+        let data = EndGameMsg { user_id, room_id };
+        self.advance_turn(data, room_id, ctx);
+    }
+
+    /// Ends the turn if `data.user_id` is drawing, then starts the next one or ends the game
+    fn advance_turn(&mut self, data: EndGameMsg, room_id: Uuid, ctx: &mut Context<Self>) {
+        if let Some(room) = self.rooms.get_mut(&room_id) {
+            let (_, turn_results, game_ended) = room.end_turn(data);
+            self.send_turn_change(room_id, turn_results, game_ended, ctx);
+        }
+    }
+
+    /// Broadcasts the results of the turn that just ended, then the next turn or the end of the game.
+    /// Does nothing if no turn ended, like when someone other than the drawer asks to end it
+    fn send_turn_change(
+        &mut self,
+        room_id: Uuid,
+        turn_results: Option<TurnResults>,
+        game_ended: bool,
+        ctx: &mut Context<Self>,
+    ) {
+        if turn_results.is_none() && !game_ended {
+            return;
+        }
+        let Some(room) = self.rooms.get_mut(&room_id) else {
+            return;
+        };
+        let drawings = std::mem::take(&mut room.drawings);
+        let room_data = room.clone();
+        self.add_to_gallery(room_id, drawings, ctx);
+        if let Some(turn_results) = turn_results {
+            self.send_message(
+                &room_id,
+                MessageTypes::Game(GameTypes::TurnResults),
+                serde_json::to_string(&turn_results).unwrap_or_default(),
+                None,
+            );
+        }
+        if game_ended {
+            println!("send end game msg");
+            self.send_message(
+                &room_id,
+                MessageTypes::Game(GameTypes::EndGame),
+                serde_json::to_string(&FinalResults::from_room(&room_data)).unwrap_or_default(),
+                None,
+            );
+            // a little after the window, so the room is surely finished by then
+            let close_after = RATING_WINDOW + Duration::from_secs(1);
+            ctx.run_later(close_after, move |act, _ctx| {
+                act.close_finished_room(room_id)
+            });
+        } else {
+            self.send_message(
+                &room_id,
+                MessageTypes::Game(GameTypes::NewTurn),
+                serde_json::to_string(&room_data).unwrap_or_default(),
+                None,
+            );
+            self.send_turn_started(&room_id);
+        }
+    }
+
+    /// Removes a room once its game is over and the last drawing can't be rated anymore.
+    /// Ratings change the drawer's score, so the final results are sent again if there were any.
+    fn close_finished_room(&mut self, room_id: Uuid) {
+        let Some(room) = self.rooms.get(&room_id).filter(|room| room.is_finished()) else {
+            return;
+        };
+        let rated = room
+            .history
+            .last()
+            .is_some_and(|turn| !turn.ratings.is_empty());
+        if rated {
             self.send_message(
                 &room_id,
-                MessageTypes::Game(GameTypes::GuessResult),
-                serde_json::to_string(&content).unwrap(),
+                MessageTypes::Game(GameTypes::EndGame),
+                serde_json::to_string(&FinalResults::from_room(room)).unwrap_or_default(),
                 None,
-            )
+            );
         }
+        self.rooms.remove(&room_id);
     }
 
-    pub fn end_turn(&mut self, msg: ClientServerMessage, room_id: Uuid) {
-        let data = serde_json::from_str::<EndGameMsg>(&msg.content);
-        if data.is_err() {
+    /// Renders finished turns' drawings on a blocking thread, then adds them to the room's gallery
+    fn add_to_gallery(&mut self, room_id: Uuid, drawings: Vec<Drawing>, ctx: &mut Context<Self>) {
+        if drawings.is_empty() || !self.galleries.contains_key(&room_id) {
             return;
         }
-        let data = data.unwrap();
-        if let Some(room) = self.rooms.get_mut(&room_id) {
-            let (content, game_ended) = room.end_turn(data);
-            if content.is_some() {
-                let room_data = content.unwrap();
-                if game_ended {
-                    println!("send end game msg");
+        let rendered = task::spawn_blocking(move || {
+            drawings
+                .into_iter()
+                .filter_map(Drawing::render)
+                .collect::<Vec<_>>()
+        });
+        ctx.spawn(rendered.into_actor(self).map(move |rendered, act, _ctx| {
+            let (Ok(drawings), Some(gallery)) = (rendered, act.galleries.get_mut(&room_id)) else {
+                return;
+            };
+            // copies the gallery only if a request is still reading the previous snapshot
+            Arc::make_mut(gallery).add(drawings);
+        }));
+    }
+
+    /// Logs and relays draw operations if they come from the current drawer.
+    /// Operations off the canvas or outside the room's palette and brushes are dropped.
+    /// Operations are relayed the way they came in, as JSON messages or as a binary frame.
+    pub fn relay_draw(&mut self, ops: Vec<DrawOp>, binary: bool, user_id: Uuid, room_id: Uuid) {
+        let Some(room) = self.rooms.get_mut(&room_id) else {
+            return;
+        };
+        if !room.can_draw(&user_id) {
+            self.send_user_message(
+                MessageTypes::Game(GameTypes::Error),
+                serde_json::to_string(&ErrorMsg {
+                    msg: "Only the current drawer can draw".to_string(),
+                })
+                .unwrap(),
+                &user_id,
+            );
+            return;
+        }
+
+        // undo and redo are applied by the drawer's client only once the server confirms them,
+        // so group the relayed operations by whether the drawer gets them too
+        let mut batches: Vec<(bool, Vec<DrawOp>)> = Vec::new();
+        let canvas = room.state.canvas.clone();
+        for op in ops.into_iter().filter(|op| op.is_valid(&canvas)) {
+            let to_drawer = matches!(op, DrawOp::Undo | DrawOp::Redo);
+            let relayed = room.record_draw(op).unwrap_or_default();
+            match batches.last_mut() {
+                Some((last_to_drawer, batch)) if *last_to_drawer == to_drawer => {
+                    batch.extend(relayed)
+                }
+                _ => batches.push((to_drawer, relayed)),
+            }
+        }
+
+        for (to_drawer, batch) in batches {
+            let skip_id = if to_drawer { None } else { Some(&user_id) };
+            if batch.is_empty() {
+                continue;
+            }
+            if binary {
+                self.send_draw_frame(&room_id, encode_ops(&batch), skip_id);
+            } else {
+                for op in batch {
                     self.send_message(
                         &room_id,
-                        MessageTypes::Game(GameTypes::EndGame),
-                        serde_json::to_string(&room_data).unwrap_or(String::new()),
-                        None,
+                        MessageTypes::Relay(RelayTypes::Draw),
+                        serde_json::to_string(&op).unwrap(),
+                        skip_id,
                     );
-                    self.rooms.remove(&room_data.room_id);
-                } else {
-                    self.send_message(
-                        &room_id,
-                        MessageTypes::Game(GameTypes::NewTurn),
-                        serde_json::to_string(&room_data).unwrap_or(String::new()),
-                        None,
-                    )
                 }
             }
         }
     }
+
+    /// Removes a player from a room, tells them they were kicked and notifies everyone else.
+    /// Used by both owner kicks and vote kicks. If they were drawing, their turn
+    /// ends and the next one starts once everyone knows they are gone.
+    pub fn kick_player(&mut self, room_id: Uuid, user_id: Uuid, ctx: &mut Context<Self>) {
+        let owner = self.rooms.get(&room_id).map(|room| room.owner);
+        let turn_change = self
+            .rooms
+            .get_mut(&room_id)
+            .filter(|room| {
+                room.turn
+                    .as_ref()
+                    .is_some_and(|turn| turn.drawer_id == user_id)
+            })
+            .map(|room| {
+                let (_, turn_results, game_ended) = room.end_turn(EndGameMsg { user_id, room_id });
+                (turn_results, game_ended)
+            });
+        let removed = self
+            .rooms
+            .get_mut(&room_id)
+            .and_then(|room| room.remove_player(&user_id));
+
+        if let Some(player) = removed {
+            self.send_user_message(
+                MessageTypes::Game(GameTypes::Kicked),
+                room_id.to_string(),
+                &user_id,
+            );
+            self.sessions.remove(&user_id);
+            self.draw_sessions.remove(&user_id);
+
+            let event = SystemEvent::PlayerLeft {
+                user_id,
+                username: player.username.clone(),
+                kicked: true,
+            };
+            let info = PlayerMovement {
+                enter: false,
+                user_id,
+                player,
+            };
+
+            self.send_message(
+                &room_id,
+                MessageTypes::Game(GameTypes::PlayerKicked),
+                serde_json::to_string(&info).unwrap(),
+                None,
+            );
+            self.send_event(&room_id, event, None);
+
+            let new_owner = self.rooms.get(&room_id).map(|room| room.owner);
+            if let Some(owner_id) = new_owner.filter(|new_owner| Some(*new_owner) != owner) {
+                let event = SystemEvent::OwnerChanged {
+                    owner_id,
+                    username: self.username(&room_id, &owner_id),
+                };
+                self.send_event(&room_id, event, None);
+            }
+        }
+        if let Some((turn_results, game_ended)) = turn_change {
+            self.send_turn_change(room_id, turn_results, game_ended, ctx);
+        }
+    }
+
+    pub fn owner_kick(
+        &mut self,
+        msg: ClientServerMessage,
+        user_id: Uuid,
+        room_id: Uuid,
+        ctx: &mut Context<Self>,
+    ) {
+        let Ok(data) = serde_json::from_str::<KickPlayerMsg>(&msg.content) else {
+            return;
+        };
+        let is_owner = self
+            .rooms
+            .get(&room_id)
+            .is_some_and(|room| room.owner == user_id);
+        if is_owner && data.target_id != user_id {
+            self.kick_player(room_id, data.target_id, ctx);
+        }
+    }
+
+    /// Runs a chat message starting with `/` as a command, replying privately.
+    /// Returns whether the message was a command.
+    pub fn run_command(
+        &mut self,
+        msg: &ClientServerMessage,
+        user_id: Uuid,
+        room_id: Uuid,
+        ctx: &mut Context<Self>,
+    ) -> bool {
+        let Ok(data) = serde_json::from_str::<ChatMsg>(&msg.content) else {
+            return false;
+        };
+        let Some(room) = self.rooms.get(&room_id) else {
+            return false;
+        };
+        let Some(command) = commands::parse(&data.content, room, &user_id) else {
+            return false;
+        };
+
+        let result = command.and_then(|command| match command {
+            Command::Kick(name) => {
+                let target_id = self
+                    .find_target(room_id, &name, &user_id)
+                    .ok_or(format!("No other player is called {}", name))?;
+                self.kick_player(room_id, target_id, ctx);
+                Ok(format!("Kicked {}", name))
+            }
+            Command::Mute(name) => {
+                let target_id = self
+                    .find_target(room_id, &name, &user_id)
+                    .ok_or(format!("No other player is called {}", name))?;
+                let mute = self
+                    .rooms
+                    .get_mut(&room_id)
+                    .and_then(|room| room.owner_mute(&user_id, &target_id))
+                    .ok_or(format!("Could not mute {}", name))?;
+                self.send_user_message(
+                    MessageTypes::Game(GameTypes::Muted),
+                    serde_json::to_string(&mute).unwrap(),
+                    &target_id,
+                );
+                Ok(format!("Muted {}", name))
+            }
+            Command::Skip => {
+                let drawer_id = self
+                    .rooms
+                    .get(&room_id)
+                    .filter(|room| room.turn.is_some())
+                    .and_then(Room::drawer_id)
+                    .ok_or("No turn is in progress".to_string())?;
+                self.advance_turn(
+                    EndGameMsg {
+                        user_id: drawer_id,
+                        room_id,
+                    },
+                    room_id,
+                    ctx,
+                );
+                Ok("Skipped the turn".to_string())
+            }
+            Command::Hint => {
+                let hint = self
+                    .rooms
+                    .get_mut(&room_id)
+                    .and_then(Room::reveal_hint)
+                    .ok_or("There are no more letters to reveal".to_string())?;
+                self.send_message(
+                    &room_id,
+                    MessageTypes::Game(GameTypes::Hint),
+                    serde_json::to_string(&HintMsg { hint: hint.clone() }).unwrap(),
+                    None,
+                );
+                Ok(format!("Revealed a letter: {}", hint))
+            }
+            Command::Rounds(rounds) => {
+                let Some(room) = self.rooms.get_mut(&room_id) else {
+                    return Err("Room not found".to_string());
+                };
+                room.set_total_rounds(rounds)?;
+                let state = serde_json::to_string(&room.state).unwrap();
+                self.send_message(
+                    &room_id,
+                    MessageTypes::Game(GameTypes::GameState),
+                    state,
+                    None,
+                );
+                Ok(format!("The game now has {} rounds", rounds))
+            }
+            Command::Help => Ok(self
+                .rooms
+                .get(&room_id)
+                .map(|room| commands::help(room, &user_id))
+                .unwrap_or_default()),
+        });
+
+        let reply = match result {
+            Ok(msg) => CommandReplyMsg { ok: true, msg },
+            Err(msg) => CommandReplyMsg { ok: false, msg },
+        };
+        self.send_user_message(
+            MessageTypes::Game(GameTypes::CommandReply),
+            serde_json::to_string(&reply).unwrap(),
+            &user_id,
+        );
+        true
+    }
+
+    /// Another player of the room, by username
+    fn find_target(&self, room_id: Uuid, username: &str, user_id: &Uuid) -> Option<Uuid> {
+        self.rooms
+            .get(&room_id)?
+            .find_player(username)
+            .filter(|target_id| target_id != user_id)
+    }
+
+    pub fn mute_player(&mut self, msg: ClientServerMessage, user_id: Uuid, room_id: Uuid) {
+        let Ok(data) = serde_json::from_str::<MutePlayerMsg>(&msg.content) else {
+            return;
+        };
+        let mute = self
+            .rooms
+            .get_mut(&room_id)
+            .and_then(|room| room.owner_mute(&user_id, &data.target_id));
+        if let Some(mute) = mute {
+            self.send_user_message(
+                MessageTypes::Game(GameTypes::Muted),
+                serde_json::to_string(&mute).unwrap(),
+                &data.target_id,
+            );
+        }
+    }
+
+    /// Delivers a whisper to its recipient only, echoing it back to the sender
+    pub fn whisper(&mut self, msg: ClientServerMessage, user_id: Uuid, room_id: Uuid) {
+        let Ok(mut data) = serde_json::from_str::<WhisperMsg>(&msg.content) else {
+            return;
+        };
+        let Some(room) = self.rooms.get(&room_id) else {
+            return;
+        };
+        if let Err(msg) = room.check_whisper(&user_id, &data.target_id) {
+            self.send_user_message(
+                MessageTypes::Game(GameTypes::Error),
+                serde_json::to_string(&ErrorMsg { msg }).unwrap(),
+                &user_id,
+            );
+            return;
+        }
+        if !self.allow_chat(&data.content, false, user_id, room_id) {
+            return;
+        }
+        let blocked_terms = self
+            .rooms
+            .get(&room_id)
+            .map(|room| room.state.blocked_terms.as_slice())
+            .unwrap_or_default();
+        match self.filter.check(&data.content, blocked_terms) {
+            Filtered::Clean => (),
+            Filtered::Masked(masked) => data.content = masked,
+            Filtered::Rejected => {
+                self.send_blocked_error(&user_id);
+                return;
+            }
+        }
+
+        let whisper = WhisperedMsg {
+            from_id: user_id,
+            from_name: self.username(&room_id, &user_id),
+            to_id: data.target_id,
+            content: data.content,
+        };
+        let content = serde_json::to_string(&whisper).unwrap();
+        self.send_user_message(
+            MessageTypes::Game(GameTypes::Whisper),
+            content.clone(),
+            &data.target_id,
+        );
+        self.send_user_message(MessageTypes::Game(GameTypes::Whisper), content, &user_id);
+    }
+
+    /// Broadcasts a guesser's reaction to the drawing
+    pub fn react(&mut self, msg: ClientServerMessage, user_id: Uuid, room_id: Uuid) {
+        let Ok(data) = serde_json::from_str::<ReactMsg>(&msg.content) else {
+            return;
+        };
+        let reaction = self
+            .rooms
+            .get_mut(&room_id)
+            .and_then(|room| room.react(&user_id, &data.emoji));
+        if let Some(reaction) = reaction {
+            self.send_message(
+                &room_id,
+                MessageTypes::Game(GameTypes::Reaction),
+                serde_json::to_string(&reaction).unwrap(),
+                None,
+            );
+        }
+    }
+
+    /// Records a rating of the last turn's drawing and broadcasts the new totals
+    pub fn rate_drawing(&mut self, msg: ClientServerMessage, user_id: Uuid, room_id: Uuid) {
+        let Ok(data) = serde_json::from_str::<RateDrawingMsg>(&msg.content) else {
+            return;
+        };
+        let rated = self
+            .rooms
+            .get_mut(&room_id)
+            .and_then(|room| room.rate_drawing(&user_id, data.up));
+        if let Some(rated) = rated {
+            self.send_message(
+                &room_id,
+                MessageTypes::Game(GameTypes::DrawingRated),
+                serde_json::to_string(&rated).unwrap(),
+                None,
+            );
+        }
+    }
+
+    pub fn start_vote_kick(
+        &mut self,
+        msg: ClientServerMessage,
+        user_id: Uuid,
+        room_id: Uuid,
+        ctx: &mut Context<Self>,
+    ) {
+        let Ok(data) = serde_json::from_str::<KickPlayerMsg>(&msg.content) else {
+            return;
+        };
+        if let Some(room) = self.rooms.get_mut(&room_id) {
+            if let Some(state) = room.start_vote_kick(user_id, data.target_id) {
+                let vote_id = room.vote_kick.as_ref().map(|vote| vote.id);
+                self.send_message(
+                    &room_id,
+                    MessageTypes::Game(GameTypes::VoteKickStarted),
+                    serde_json::to_string(&state).unwrap(),
+                    None,
+                );
+
+                ctx.run_later(VOTE_KICK_TIMEOUT, move |act, ctx| {
+                    let still_open = act
+                        .rooms
+                        .get(&room_id)
+                        .and_then(|room| room.vote_kick.as_ref())
+                        .is_some_and(|vote| Some(vote.id) == vote_id);
+                    if still_open {
+                        act.finish_vote_kick(room_id, false, ctx);
+                    }
+                });
+
+                self.check_vote_kick(room_id, ctx);
+            }
+        }
+    }
+
+    pub fn cast_vote_kick(
+        &mut self,
+        msg: ClientServerMessage,
+        user_id: Uuid,
+        room_id: Uuid,
+        ctx: &mut Context<Self>,
+    ) {
+        let Ok(data) = serde_json::from_str::<VoteKickBallotMsg>(&msg.content) else {
+            return;
+        };
+        if let Some(room) = self.rooms.get_mut(&room_id) {
+            if let Some(state) = room.cast_vote_kick(user_id, data.yes) {
+                self.send_message(
+                    &room_id,
+                    MessageTypes::Game(GameTypes::VoteKickProgress),
+                    serde_json::to_string(&state).unwrap(),
+                    None,
+                );
+                self.check_vote_kick(room_id, ctx);
+            }
+        }
+    }
+
+    /// Ends the vote kick early if it has been decided
+    fn check_vote_kick(&mut self, room_id: Uuid, ctx: &mut Context<Self>) {
+        let outcome = self
+            .rooms
+            .get(&room_id)
+            .and_then(|room| room.vote_kick_outcome());
+        if let Some(kicked) = outcome {
+            self.finish_vote_kick(room_id, kicked, ctx);
+        }
+    }
+
+    fn finish_vote_kick(&mut self, room_id: Uuid, kicked: bool, ctx: &mut Context<Self>) {
+        let result = self
+            .rooms
+            .get_mut(&room_id)
+            .and_then(|room| room.finish_vote_kick(kicked));
+        if let Some(result) = result {
+            self.send_message(
+                &room_id,
+                MessageTypes::Game(GameTypes::VoteKickResult),
+                serde_json::to_string(&result).unwrap(),
+                None,
+            );
+            if result.kicked {
+                self.kick_player(room_id, result.target_id, ctx);
+            }
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -206,9 +977,34 @@ struct PlayerMovement {
 impl Handler<Connect> for Server {
     type Result = ();
     fn handle(&mut self, msg: Connect, _ctx: &mut Self::Context) {
+        let banned = self
+            .rooms
+            .get(&msg.room_id)
+            .is_some_and(|room| room.banned.contains(&msg.user_id));
+        if banned {
+            msg.addr.do_send(ClientServerMessage {
+                msg_type: MessageTypes::Game(GameTypes::Kicked),
+                content: msg.room_id.to_string(),
+            });
+            return;
+        }
+
         self.sessions.insert(msg.user_id, msg.addr);
+        self.draw_sessions.insert(msg.user_id, msg.draw_addr);
 
+        let reconnected = self.get_player_details(msg.user_id, msg.room_id).is_some();
         let player = self.join_room(msg.user_id, msg.room_id, msg.username);
+        let event = if reconnected {
+            SystemEvent::PlayerReconnected {
+                user_id: msg.user_id,
+                username: player.username.clone(),
+            }
+        } else {
+            SystemEvent::PlayerJoined {
+                user_id: msg.user_id,
+                username: player.username.clone(),
+            }
+        };
 
         let info = PlayerMovement {
             enter: true,
@@ -216,6 +1012,25 @@ impl Handler<Connect> for Server {
             player,
         };
 
+        // catch the new session up on the chat before it shows up in it,
+        // and remind a reconnecting player of their mute
+        if let Some(room) = self.rooms.get_mut(&msg.room_id) {
+            let mute = room.active_mute(&msg.user_id);
+            let history = serde_json::to_string(&room.chat_history).unwrap_or_default();
+            self.send_user_message(
+                MessageTypes::Game(GameTypes::ChatHistory),
+                history,
+                &msg.user_id,
+            );
+            if let Some(mute) = mute {
+                self.send_user_message(
+                    MessageTypes::Game(GameTypes::Muted),
+                    serde_json::to_string(&mute).unwrap(),
+                    &msg.user_id,
+                );
+            }
+        }
+
         // notify all users in same room
         self.send_message(
             &msg.room_id,
@@ -223,13 +1038,28 @@ impl Handler<Connect> for Server {
             serde_json::to_string(&info).unwrap(),
             Some(&msg.user_id),
         );
+        self.send_event(&msg.room_id, event, Some(&msg.user_id));
+
+        // bring the new session's canvas up to date
+        if let Some(room) = self.rooms.get(&msg.room_id) {
+            self.send_user_message(
+                MessageTypes::Game(GameTypes::CanvasSettings),
+                serde_json::to_string(&room.state.canvas).unwrap_or_default(),
+                &msg.user_id,
+            );
+            if !room.strokes.is_empty() {
+                self.send_canvas_replay(msg.room_id, msg.user_id);
+            }
+        }
     }
 }
 
 impl Handler<Disconnect> for Server {
     type Result = ();
     fn handle(&mut self, msg: Disconnect, _ctx: &mut Self::Context) {
+        self.draw_sessions.remove(&msg.user_id);
         if self.sessions.remove(&msg.user_id).is_some() {
+            let username = self.username(&msg.room_id, &msg.user_id);
             // self.rooms
             //     .get_mut(&msg.room_id)
             //     .unwrap()
@@ -243,11 +1073,8 @@ impl Handler<Disconnect> for Server {
                     }
                     // room.players.remove(&msg.user_id);
                 } else {
-                    let no_other_active_players = room
-                        .players
-                        .iter()
-                        .find(|(_, player)| (*player).active)
-                        .is_none();
+                    let no_other_active_players =
+                        room.players.values().all(|player| !player.active);
                     if no_other_active_players {
                         println!("Close room");
                         self.rooms.remove(&msg.room_id);
@@ -266,14 +1093,20 @@ impl Handler<Disconnect> for Server {
                 MessageTypes::Game(GameTypes::PlayerLeft),
                 serde_json::to_string(&info).unwrap(),
                 Some(&msg.user_id),
-            )
+            );
+            let event = SystemEvent::PlayerLeft {
+                user_id: msg.user_id,
+                username,
+                kicked: false,
+            };
+            self.send_event(&msg.room_id, event, Some(&msg.user_id));
         }
     }
 }
 
 impl Handler<UserMessage> for Server {
     type Result = ();
-    fn handle(&mut self, msg: UserMessage, _ctx: &mut Self::Context) {
+    fn handle(&mut self, msg: UserMessage, ctx: &mut Self::Context) {
         if let Ok(data) = serde_json::from_str::<ClientServerMessage>(&msg.msg) {
             if data.msg_type == MessageTypes::Game(GameTypes::StartGame) {
                 if let Ok(start_msg) = serde_json::from_str::<StartGameMsg>(&data.content) {
@@ -281,31 +1114,67 @@ impl Handler<UserMessage> for Server {
                 }
             } else if data.msg_type == MessageTypes::Game(GameTypes::EndTurn) {
                 println!("end turn msg received");
-                self.end_turn(data, msg.room_id);
+                self.end_turn(msg.user_id, msg.room_id, ctx);
             } else if data.msg_type == MessageTypes::Game(GameTypes::Guess) {
-                self.validate_guess(data, msg.room_id);
-            } else {
-                self.send_message(
-                    &msg.room_id,
-                    data.msg_type,
-                    data.content,
-                    Some(&msg.user_id),
-                )
+                if !self.run_command(&data, msg.user_id, msg.room_id, ctx) {
+                    self.guess_or_chat(data, msg.user_id, msg.room_id);
+                }
+            } else if data.msg_type == MessageTypes::Game(GameTypes::KickPlayer) {
+                self.owner_kick(data, msg.user_id, msg.room_id, ctx);
+            } else if data.msg_type == MessageTypes::Game(GameTypes::MutePlayer) {
+                self.mute_player(data, msg.user_id, msg.room_id);
+            } else if data.msg_type == MessageTypes::Game(GameTypes::Whisper) {
+                self.whisper(data, msg.user_id, msg.room_id);
+            } else if data.msg_type == MessageTypes::Game(GameTypes::React) {
+                self.react(data, msg.user_id, msg.room_id);
+            } else if data.msg_type == MessageTypes::Game(GameTypes::RateDrawing) {
+                self.rate_drawing(data, msg.user_id, msg.room_id);
+            } else if data.msg_type == MessageTypes::Game(GameTypes::StartVoteKick) {
+                self.start_vote_kick(data, msg.user_id, msg.room_id, ctx);
+            } else if data.msg_type == MessageTypes::Game(GameTypes::VoteKick) {
+                self.cast_vote_kick(data, msg.user_id, msg.room_id, ctx);
+            } else if data.msg_type == MessageTypes::Relay(RelayTypes::Text) {
+                if !self.run_command(&data, msg.user_id, msg.room_id, ctx) {
+                    self.send_chat(data, msg.user_id, msg.room_id);
+                }
+            } else if data.msg_type == MessageTypes::Relay(RelayTypes::Draw) {
+                if let Some(op) = DrawOp::parse(&data.content) {
+                    self.relay_draw(vec![op], false, msg.user_id, msg.room_id);
+                }
             }
         };
     }
 }
 
+impl Handler<UserDrawFrame> for Server {
+    type Result = ();
+    fn handle(&mut self, msg: UserDrawFrame, _ctx: &mut Self::Context) {
+        self.relay_draw(msg.ops, true, msg.user_id, msg.room_id);
+    }
+}
+
+impl Handler<GetGallery> for Server {
+    type Result = Option<Arc<Gallery>>;
+
+    fn handle(&mut self, msg: GetGallery, _ctx: &mut Self::Context) -> Self::Result {
+        self.galleries.get(&msg.room_id).cloned()
+    }
+}
+
+impl Handler<ResyncCanvas> for Server {
+    type Result = ();
+    fn handle(&mut self, msg: ResyncCanvas, _ctx: &mut Self::Context) {
+        self.send_canvas_replay(msg.room_id, msg.user_id);
+    }
+}
+
 impl Handler<RoomDetails> for Server {
     type Result = String;
 
     fn handle(&mut self, msg: RoomDetails, _ctx: &mut Self::Context) -> Self::Result {
-        let details = self.get_room_details(msg.0);
-        if details.is_some() {
-            serde_json::to_string(details.unwrap()).unwrap_or(String::new())
-        } else {
-            String::new()
-        }
+        self.get_room_details(msg.0)
+            .map(|details| serde_json::to_string(details).unwrap_or_default())
+            .unwrap_or_default()
     }
 }
 
@@ -320,12 +1189,12 @@ impl Handler<GetPlayerDetails> for Server {
 
     fn handle(&mut self, msg: GetPlayerDetails, _ctx: &mut Self::Context) -> Self::Result {
         let details = self.get_player_details(msg.user_id, msg.room_id);
-        if details.is_some() {
+        if let Some(player) = details {
             let res = PlayerDetails {
                 user_id: msg.user_id,
-                player: details.unwrap().clone(),
+                player: player.clone(),
             };
-            serde_json::to_string(&res).unwrap_or(String::new())
+            serde_json::to_string(&res).unwrap_or_default()
             // self.send_user_message(
             //     MessageTypes::Data(DataTypes::UserData),
             //     serde_json::to_string(&res).unwrap(),
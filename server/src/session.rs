@@ -1,10 +1,20 @@
-use std::time::{Duration, Instant};
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
 
 use actix::prelude::*;
+use actix_web::web::Bytes;
 use actix_web_actors::ws;
 use uuid::Uuid;
 
-use crate::messages::{self, ClientServerMessage, MessageTypes, UserMessage};
+use crate::codec::decode_ops;
+use crate::draw::DrawOp;
+use crate::messages::{
+    self, ClientServerMessage, DrawFrame, ErrorMsg, GameTypes, MessageTypes, RelayTypes,
+    UserDrawFrame, UserMessage,
+};
+use crate::rate_limit::{RateLimitConfig, TokenBucket};
 use crate::server::Server;
 
 /// How often heartbeat pings are sent
@@ -13,6 +23,22 @@ const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
 /// How long before lack of client response causes a timeout
 const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
 
+/// How often queued draw relays are written to the client
+const DRAW_FLUSH_INTERVAL: Duration = Duration::from_millis(16);
+
+/// Most draw relays written to the client per flush
+const DRAW_FRAMES_PER_FLUSH: usize = 8;
+
+/// Largest binary draw frame accepted from a client, a few hundred operations
+const MAX_DRAW_FRAME_BYTES: usize = 2048;
+
+/// A draw relay waiting to be written to the client
+#[derive(Debug)]
+pub enum QueuedDraw {
+    Binary(Bytes),
+    Text(String),
+}
+
 #[derive(Debug)]
 pub struct UserSession {
     pub id: Uuid,
@@ -20,6 +46,17 @@ pub struct UserSession {
     pub hb: Instant,
     pub room: Uuid,
     pub addr: Addr<Server>,
+    pub limits: RateLimitConfig,
+    pub draw_bucket: TokenBucket,
+    pub chat_bucket: TokenBucket,
+    pub guess_bucket: TokenBucket,
+    pub reaction_bucket: TokenBucket,
+    pub violations: u32,
+    pub violations_since: Instant,
+    /// Draw relays waiting to be written, oldest first
+    pub pending_draw_frames: VecDeque<QueuedDraw>,
+    /// Set when frames were dropped, so the canvas gets resynced
+    pub dropped_draw_frames: bool,
 }
 
 impl UserSession {
@@ -50,11 +87,102 @@ impl UserSession {
         });
     }
 
+    /// helper method that writes queued draw relays to the client.
+    ///
+    /// this is pacing, not backpressure: relays are handed to the websocket context at
+    /// DRAW_FRAMES_PER_FLUSH every DRAW_FLUSH_INTERVAL (500 a second) whether or not the
+    /// client has read the previous ones, as the context doesn't report its write progress.
+    /// relays are only dropped when they arrive faster than that, and then the canvas is
+    /// replayed once the queue has drained. a client on a slow connection still has
+    /// everything buffered for it, bounded only by the drawer's draw rate limit
+    fn flush_draw_frames(&self, ctx: &mut ws::WebsocketContext<Self>) {
+        ctx.run_interval(DRAW_FLUSH_INTERVAL, |act, ctx| {
+            for _ in 0..DRAW_FRAMES_PER_FLUSH {
+                match act.pending_draw_frames.pop_front() {
+                    Some(QueuedDraw::Binary(frame)) => ctx.binary(frame),
+                    Some(QueuedDraw::Text(text)) => ctx.text(text),
+                    None => break,
+                }
+            }
+
+            if act.dropped_draw_frames && act.pending_draw_frames.is_empty() {
+                act.dropped_draw_frames = false;
+                act.addr.do_send(messages::ResyncCanvas {
+                    user_id: act.id,
+                    room_id: act.room,
+                });
+            }
+        });
+    }
+
+    /// Records a rate limit hit. Returns false once the session should be disconnected.
+    fn record_violation(&mut self) -> bool {
+        let window = Duration::from_secs(self.limits.violation_window_secs);
+        if self.violations_since.elapsed() > window {
+            self.violations = 0;
+            self.violations_since = Instant::now();
+        }
+        self.violations += 1;
+        self.violations <= self.limits.max_violations
+    }
+
+    /// Queues a draw relay, dropping the oldest one when the queue is full
+    fn queue_draw(&mut self, draw: QueuedDraw) {
+        if self.pending_draw_frames.len() >= self.limits.max_pending_draw_frames {
+            self.pending_draw_frames.pop_front();
+            self.dropped_draw_frames = true;
+        }
+        self.pending_draw_frames.push_back(draw);
+    }
+
+    /// Decodes a binary draw frame and charges a draw token per operation
+    fn allow_draw_frame(&mut self, bin: &Bytes) -> Option<Vec<DrawOp>> {
+        if bin.len() > MAX_DRAW_FRAME_BYTES {
+            return None;
+        }
+        let ops = decode_ops(bin)?;
+        self.draw_bucket.try_take_n(ops.len()).then_some(ops)
+    }
+
+    /// Checks the rate limit for a text message, replying with an error when it is hit
+    fn allow_text(&mut self, msg: &str, ctx: &mut ws::WebsocketContext<Self>) -> bool {
+        let Ok(data) = serde_json::from_str::<ClientServerMessage>(msg) else {
+            return true;
+        };
+        let bucket = match data.msg_type {
+            MessageTypes::Relay(RelayTypes::Draw) => &mut self.draw_bucket,
+            MessageTypes::Relay(RelayTypes::Text) | MessageTypes::Game(GameTypes::Whisper) => {
+                &mut self.chat_bucket
+            }
+            MessageTypes::Game(GameTypes::Guess) => &mut self.guess_bucket,
+            MessageTypes::Game(GameTypes::React | GameTypes::RateDrawing) => {
+                &mut self.reaction_bucket
+            }
+            _ => return true,
+        };
+        if bucket.try_take() {
+            return true;
+        }
+
+        if data.msg_type != MessageTypes::Relay(RelayTypes::Draw) {
+            let res = ClientServerMessage {
+                msg_type: MessageTypes::Game(GameTypes::Error),
+                content: serde_json::to_string(&ErrorMsg {
+                    msg: "You are sending messages too quickly".to_string(),
+                })
+                .unwrap(),
+            };
+            ctx.text(serde_json::to_string(&res).unwrap_or(String::from("{}")));
+        }
+        false
+    }
+
     pub fn new(
         room: Uuid,
         addr: Addr<Server>,
         id: Option<Uuid>,
         username: Option<String>,
+        limits: RateLimitConfig,
     ) -> UserSession {
         let mut session = UserSession {
             id: Uuid::new_v4(),
@@ -62,13 +190,22 @@ impl UserSession {
             room,
             hb: Instant::now(),
             addr,
+            limits,
+            draw_bucket: TokenBucket::new(limits.draw),
+            chat_bucket: TokenBucket::new(limits.chat),
+            guess_bucket: TokenBucket::new(limits.guess),
+            reaction_bucket: TokenBucket::new(limits.reaction),
+            violations: 0,
+            violations_since: Instant::now(),
+            pending_draw_frames: VecDeque::new(),
+            dropped_draw_frames: false,
         };
-        if id.is_some() {
-            session.id = id.unwrap();
+        if let Some(id) = id {
+            session.id = id;
         };
 
-        if username.is_some() {
-            session.username = username.unwrap();
+        if let Some(username) = username {
+            session.username = username;
         };
 
         session
@@ -83,12 +220,14 @@ impl Actor for UserSession {
     fn started(&mut self, ctx: &mut Self::Context) {
         // we'll start heartbeat process on session start.
         self.hb(ctx);
+        self.flush_draw_frames(ctx);
 
         let addr = ctx.address();
 
         self.addr
             .send(messages::Connect {
-                addr: addr.recipient(),
+                addr: addr.clone().recipient(),
+                draw_addr: addr.recipient(),
                 user_id: self.id,
                 username: self.username.clone(),
                 room_id: self.room,
@@ -124,7 +263,19 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for UserSession {
             Ok(ws::Message::Pong(_)) => {
                 self.hb = Instant::now();
             }
-            Ok(ws::Message::Binary(bin)) => ctx.binary(bin),
+            Ok(ws::Message::Binary(bin)) => {
+                if let Some(ops) = self.allow_draw_frame(&bin) {
+                    self.addr.do_send(UserDrawFrame {
+                        user_id: self.id,
+                        ops,
+                        room_id: self.room,
+                    })
+                } else if !self.record_violation() {
+                    println!("Websocket Client exceeded rate limits, disconnecting!");
+                    ctx.close(None);
+                    ctx.stop();
+                }
+            }
             Ok(ws::Message::Close(reason)) => {
                 ctx.close(reason);
                 ctx.stop();
@@ -134,7 +285,7 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for UserSession {
             }
             Ok(ws::Message::Nop) => (),
             Ok(ws::Message::Text(s)) => {
-                if s.to_string() == "GET_ID".to_string() {
+                if &*s == "GET_ID" {
                     let res = ClientServerMessage {
                         msg_type: MessageTypes::Data(messages::DataTypes::UserID),
                         content: self.id.to_string(),
@@ -145,12 +296,16 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for UserSession {
                     //     room_id: self.room,
                     // });
                     ctx.text(serde_json::to_string(&res).unwrap_or(String::from("{}")))
-                } else {
+                } else if self.allow_text(&s, ctx) {
                     self.addr.do_send(UserMessage {
                         user_id: self.id,
                         msg: s.to_string(),
                         room_id: self.room,
                     })
+                } else if !self.record_violation() {
+                    println!("Websocket Client exceeded rate limits, disconnecting!");
+                    ctx.close(None);
+                    ctx.stop();
                 }
             }
 
@@ -163,6 +318,33 @@ impl Handler<ClientServerMessage> for UserSession {
     type Result = ();
 
     fn handle(&mut self, msg: ClientServerMessage, ctx: &mut Self::Context) {
-        ctx.text(serde_json::to_string(&msg).unwrap_or(String::from("{}")));
+        let text = serde_json::to_string(&msg).unwrap_or(String::from("{}"));
+        match msg.msg_type {
+            MessageTypes::Relay(RelayTypes::Draw) => {
+                self.queue_draw(QueuedDraw::Text(text));
+                return;
+            }
+            // both redraw the canvas from scratch, so queued relays are already part of them
+            MessageTypes::Game(GameTypes::CanvasReplay | GameTypes::NewTurn) => {
+                self.pending_draw_frames.clear();
+                self.dropped_draw_frames = false;
+            }
+            _ => (),
+        }
+        ctx.text(text);
+
+        // the server has already dropped this session, so close the socket
+        if msg.msg_type == MessageTypes::Game(messages::GameTypes::Kicked) {
+            ctx.close(None);
+            ctx.stop();
+        }
+    }
+}
+
+impl Handler<DrawFrame> for UserSession {
+    type Result = ();
+
+    fn handle(&mut self, msg: DrawFrame, _ctx: &mut Self::Context) {
+        self.queue_draw(QueuedDraw::Binary(msg.0));
     }
 }